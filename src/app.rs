@@ -1,9 +1,67 @@
+use std::error::Error;
+use std::fmt;
+use gfx;
+use gfx::Factory;
 use glutin;
+use glutin::GlContext;
 use gfx_window_glutin;
 use gfx_device_gl;
 
 use render;
-use context::Context;
+use context::{Context, Surface};
+
+#[derive(Debug)]
+pub enum HeadlessError {
+    Context(glutin::CreationError),
+    MakeCurrent(glutin::ContextError),
+    Texture(gfx::texture::CreationError),
+    RenderTarget(gfx::TargetViewError),
+    DepthStencil(gfx::CombinedError),
+}
+
+impl fmt::Display for HeadlessError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            HeadlessError::Context(ref e) => write!(f, "cannot create a headless GL context: {}", e),
+            HeadlessError::MakeCurrent(ref e) => write!(f, "cannot make the GL context current: {}", e),
+            HeadlessError::Texture(ref e) => write!(f, "cannot create the offscreen texture: {}", e),
+            HeadlessError::RenderTarget(ref e) => write!(f, "cannot render to the offscreen texture: {}", e),
+            HeadlessError::DepthStencil(ref e) => write!(f, "cannot create the offscreen depth buffer: {}", e),
+        }
+    }
+}
+
+impl Error for HeadlessError {}
+
+impl From<glutin::CreationError> for HeadlessError {
+    fn from(e: glutin::CreationError) -> HeadlessError {
+        HeadlessError::Context(e)
+    }
+}
+
+impl From<glutin::ContextError> for HeadlessError {
+    fn from(e: glutin::ContextError) -> HeadlessError {
+        HeadlessError::MakeCurrent(e)
+    }
+}
+
+impl From<gfx::texture::CreationError> for HeadlessError {
+    fn from(e: gfx::texture::CreationError) -> HeadlessError {
+        HeadlessError::Texture(e)
+    }
+}
+
+impl From<gfx::TargetViewError> for HeadlessError {
+    fn from(e: gfx::TargetViewError) -> HeadlessError {
+        HeadlessError::RenderTarget(e)
+    }
+}
+
+impl From<gfx::CombinedError> for HeadlessError {
+    fn from(e: gfx::CombinedError) -> HeadlessError {
+        HeadlessError::DepthStencil(e)
+    }
+}
 
 pub struct App {}
 
@@ -12,6 +70,7 @@ impl App {
         title: &str,
         width: u32,
         height: u32,
+        vsync: bool,
     ) -> (
         gfx_device_gl::Device,
         gfx_device_gl::Factory,
@@ -19,7 +78,7 @@ impl App {
         Context,
     ) {
         let events_loop = glutin::EventsLoop::new();
-        let context = glutin::ContextBuilder::new();
+        let context = glutin::ContextBuilder::new().with_vsync(vsync);
         let builder = glutin::WindowBuilder::new()
             .with_title(title.to_string())
             .with_dimensions(width, height);
@@ -32,7 +91,55 @@ impl App {
                 &events_loop,
             );
 
-        let context = Context::new(window, width as i32, height as i32, render_target, depth_stencil);
+        let context = Context::new(
+            Surface::Window(window),
+            width as i32,
+            height as i32,
+            render_target,
+            depth_stencil,
+        );
         (device, factory, events_loop, context)
     }
+
+    /// Creates an offscreen GL 3.3 core context with a `width` x `height`
+    /// render target that can be read back with `capture::read_target`.
+    pub fn init_headless(
+        width: u32,
+        height: u32,
+    ) -> Result<(gfx_device_gl::Device, gfx_device_gl::Factory, Context), HeadlessError> {
+        let headless = glutin::HeadlessRendererBuilder::new(width, height)
+            .with_gl(glutin::GlRequest::Specific(glutin::Api::OpenGl, (3, 3)))
+            .with_gl_profile(glutin::GlProfile::Core)
+            .build()?;
+        unsafe { headless.make_current()? };
+        let (device, mut factory) =
+            gfx_device_gl::create(|s| headless.get_proc_address(s) as *const _);
+
+        // `create_render_target` does not allow copying out of the texture
+        let kind = gfx::texture::Kind::D2(
+            width as gfx::texture::Size,
+            height as gfx::texture::Size,
+            gfx::texture::AaMode::Single,
+        );
+        let color = factory
+            .create_texture::<<render::ColorFormat as gfx::format::Formatted>::Surface>(
+                kind,
+                1,
+                gfx::RENDER_TARGET | gfx::TRANSFER_SRC,
+                gfx::memory::Usage::Data,
+                Some(gfx::format::ChannelType::Srgb),
+            )?;
+        let render_target = factory.view_texture_as_render_target(&color, 0, None)?;
+        let depth_stencil = factory
+            .create_depth_stencil_view_only(width as gfx::texture::Size, height as gfx::texture::Size)?;
+
+        let context = Context::new(
+            Surface::Headless(headless),
+            width as i32,
+            height as i32,
+            render_target,
+            depth_stencil,
+        );
+        Ok((device, factory, context))
+    }
 }
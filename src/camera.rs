@@ -1,5 +1,34 @@
 use cgmath::prelude::*;
-use cgmath::{Deg, Matrix4, PerspectiveFov, Point3, Vector3};
+use cgmath::{self, Deg, Matrix3, Matrix4, PerspectiveFov, Point3, Quaternion, Vector3, Vector4};
+
+/// Speed multiplier while sprinting.
+const SPRINT_FACTOR: f32 = 3.0;
+/// How far ahead of the camera the target is when orbiting starts.
+const ORBIT_DISTANCE: f32 = 5.0;
+/// The closest an orbiting camera gets to its target.
+const MIN_ORBIT_DISTANCE: f32 = 0.1;
+/// Fraction of the view height one scroll line zooms an orthographic camera.
+const ORTHO_ZOOM_SPEED: f32 = 0.1;
+
+/// How the camera maps view space to clip space.
+///
+/// There is no reverse-Z variant: gfx_device_gl exposes no
+/// `glClipControl` (ARB_clip_control), so clip space depth stays -1..1 and
+/// remapping it to 1..0 would gain nothing, and the window's depth buffer is
+/// 24-bit fixed point `DepthStencil` rather than floating point. `pipe` and
+/// `lamp_pipe` therefore keep testing with `LESS_EQUAL_WRITE`.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Projection {
+    /// Perspective between the near and far planes.
+    Perspective,
+    /// Perspective with the far plane at infinity, so that nothing is
+    /// clipped however far away. Depth precision still falls off with the
+    /// distance, so keep the near plane as far out as the scene allows.
+    InfinitePerspective,
+    /// Parallel projection of a view volume `height` units high between the
+    /// near and far planes.
+    Orthographic { height: f32 },
+}
 
 
 #[derive(Debug, Copy, Clone)]
@@ -8,6 +37,15 @@ pub enum MovementDirection {
     Down,
     Left,
     Right,
+    Ascend,
+    Descend,
+}
+
+/// The point an orbit camera circles and how far away it is.
+#[derive(Debug, Copy, Clone)]
+struct Orbit {
+    target: Point3<f32>,
+    distance: f32,
 }
 
 #[derive(Debug, Clone)]
@@ -22,11 +60,29 @@ pub struct Camera {
     pitch: f32,
     aspect: f32,
     fov: f32,
-    movement: [f32; 4],
+    fov_range: (f32, f32),
+    height_range: (f32, f32),
+    near: f32,
+    far: f32,
+    projection: Projection,
+    movement: [f32; 6],
+    sprinting: bool,
+    /// Orientation in 6-DoF mode, where yaw and pitch only follow `front`.
+    free: Option<Quaternion<f32>>,
+    /// Set in orbit mode, where the position follows from yaw and pitch.
+    orbit: Option<Orbit>,
 }
 
 impl Camera {
     fn update_vectors(&mut self) {
+        if let Some(orientation) = self.free {
+            self.front = orientation.rotate_vector(-Vector3::unit_z());
+            self.up = orientation.rotate_vector(Vector3::unit_y());
+            self.right = orientation.rotate_vector(Vector3::unit_x());
+            self.pitch = Deg::asin(self.front.y.clamp(-1.0, 1.0)).0;
+            self.yaw = Deg::atan2(self.front.z, self.front.x).0;
+            return;
+        }
         self.front = Vector3::new(
             Deg(self.yaw).cos() * Deg(self.pitch).cos(),
             Deg(self.pitch).sin(),
@@ -34,12 +90,18 @@ impl Camera {
         ).normalize();
         self.right = self.front.cross(self.world_up).normalize();
         self.up = self.right.cross(self.front).normalize();
+        if let Some(orbit) = self.orbit {
+            self.pos = orbit.target + self.front * -orbit.distance;
+        }
     }
 
     fn movement_vector(&self) -> Option<Vector3<f32>> {
         let x = (self.movement[2] - self.movement[3]) * self.right;
         let z = (self.movement[0] - self.movement[1]) * self.front;
-        let v = x + z;
+        // straight up in 6-DoF mode is wherever the camera's top points
+        let up = if self.free.is_some() { self.up } else { self.world_up };
+        let y = (self.movement[4] - self.movement[5]) * up;
+        let v = x + y + z;
         if !v.is_zero() {
             Some(v.normalize())
         } else {
@@ -53,6 +115,8 @@ impl Camera {
             MovementDirection::Down => 1,
             MovementDirection::Left => 3,
             MovementDirection::Right => 2,
+            MovementDirection::Ascend => 4,
+            MovementDirection::Descend => 5,
         };
         self.movement[index] = if m { 1.0 } else { 0.0 };
     }
@@ -62,22 +126,111 @@ impl Camera {
         self.speed = speed;
     }
 
+    pub fn sprint(&mut self, sprinting: bool) {
+        self.sprinting = sprinting;
+    }
+
     pub fn move_for(&mut self, ts: f32) {
         if let Some(v) = self.movement_vector() {
-            self.pos += v * self.speed * ts;
+            let factor = if self.sprinting { SPRINT_FACTOR } else { 1.0 };
+            self.pos += v * self.speed * factor * ts;
         }
     }
 
-    #[allow(dead_code)]
-    pub fn look_around(&mut self, mut pitch: f32, yaw: f32) {
-        if pitch > 89.0 {
-            pitch = 89.0;
+    pub fn look_around(&mut self, pitch: f32, yaw: f32) {
+        self.pitch = pitch.clamp(-89.0, 89.0);
+        self.yaw = yaw;
+        self.update_vectors();
+    }
+
+    /// Turns by the given degrees, positive to the right and up. In 6-DoF
+    /// mode the angles are about the camera's own axes and pitch is not
+    /// clamped; otherwise roll is ignored.
+    pub fn turn(&mut self, yaw: f32, pitch: f32, roll: f32) {
+        match self.free {
+            Some(orientation) => {
+                let turn = Quaternion::from_angle_y(Deg(-yaw))
+                    * Quaternion::from_angle_x(Deg(pitch))
+                    * Quaternion::from_angle_z(Deg(-roll));
+                self.free = Some((orientation * turn).normalize());
+                self.update_vectors();
+            }
+            None => {
+                let (pitch, yaw) = (self.pitch + pitch, self.yaw + yaw);
+                self.look_around(pitch, yaw);
+            }
         }
-        if pitch < -89.0 {
-            pitch = -89.0;
+    }
+
+    /// Switches between yaw/pitch and 6-DoF mode, keeping the direction
+    /// the camera looks in. Leaving 6-DoF mode drops the roll, entering it
+    /// leaves orbit mode.
+    pub fn set_free(&mut self, free: bool) {
+        if free == self.free.is_some() {
+            return;
+        }
+        self.orbit = None;
+        self.free = if free {
+            Some(Quaternion::from(Matrix3::from_cols(self.right, self.up, -self.front)))
+        } else {
+            None
+        };
+        if !free {
+            self.pitch = self.pitch.clamp(-89.0, 89.0);
+        }
+        self.update_vectors();
+    }
+
+    pub fn is_free(&self) -> bool {
+        self.free.is_some()
+    }
+
+    /// Switches between flying and orbiting a target straight ahead,
+    /// keeping the current view. Orbiting leaves 6-DoF mode.
+    pub fn set_orbit(&mut self, orbit: bool) {
+        if orbit == self.orbit.is_some() {
+            return;
+        }
+        if !orbit {
+            self.orbit = None;
+            return;
+        }
+        self.set_free(false);
+        self.orbit = Some(Orbit {
+            target: self.pos + self.front * ORBIT_DISTANCE,
+            distance: ORBIT_DISTANCE,
+        });
+        self.update_vectors();
+    }
+
+    /// Puts the camera at `pos` looking along `yaw` and `pitch`, leaving
+    /// 6-DoF and orbit mode.
+    pub fn set_view(&mut self, pos: Point3<f32>, yaw: f32, pitch: f32, fov: f32) {
+        self.free = None;
+        self.orbit = None;
+        self.pos = pos;
+        self.fov = fov;
+        self.look_around(pitch, yaw);
+    }
+
+    pub fn is_orbiting(&self) -> bool {
+        self.orbit.is_some()
+    }
+
+    /// Moves an orbiting camera `amount` of its distance towards the target.
+    pub fn dolly(&mut self, amount: f32) {
+        if let Some(ref mut orbit) = self.orbit {
+            orbit.distance = (orbit.distance * (1.0 - amount)).max(MIN_ORBIT_DISTANCE);
+        }
+        self.update_vectors();
+    }
+
+    /// Moves an orbiting camera and its target sideways and up by the given
+    /// fractions of the distance between them.
+    pub fn pan(&mut self, right: f32, up: f32) {
+        if let Some(ref mut orbit) = self.orbit {
+            orbit.target += (self.right * right + self.up * up) * orbit.distance;
         }
-        self.pitch = pitch;
-        self.yaw = yaw;
         self.update_vectors();
     }
 
@@ -85,34 +238,91 @@ impl Camera {
         Matrix4::look_at(self.pos, self.pos + self.front, self.up)
     }
 
-    #[allow(dead_code)]
     pub fn update_aspect(&mut self, width: f32, height: f32) {
         self.aspect = width / height;
     }
 
+    /// Narrows the field of view by `zoom` degrees, or shrinks the view
+    /// volume of an orthographic camera.
     pub fn zoom(&mut self, zoom: f32) {
-        self.fov -= zoom;
-        if self.fov > 45.0 {
-            self.fov = 45.0
-        }
-        if self.fov < 1.0 {
-            self.fov = 1.0
+        match self.projection {
+            Projection::Orthographic { ref mut height } => {
+                let (min, max) = self.height_range;
+                *height = (*height * (1.0 - zoom * ORTHO_ZOOM_SPEED)).clamp(min, max);
+            }
+            _ => self.fov = (self.fov - zoom).clamp(self.fov_range.0, self.fov_range.1),
         }
     }
 
     pub fn projection_matrix(&self) -> Matrix4<f32> {
-        PerspectiveFov {
-            fovy: Deg(self.fov).into(),
-            aspect: self.aspect,
-            near: 0.1,
-            far: 100.0,
-        }.into()
+        match self.projection {
+            Projection::Perspective => PerspectiveFov {
+                fovy: Deg(self.fov).into(),
+                aspect: self.aspect,
+                near: self.near,
+                far: self.far,
+            }.into(),
+            Projection::InfinitePerspective => {
+                // the perspective matrix with far going to infinity: clip z
+                // = distance - 2 * near, so NDC z runs from -1 at the near
+                // plane towards 1 at infinity
+                let f = 1.0 / Deg(self.fov / 2.0).tan();
+                Matrix4::from_cols(
+                    Vector4::new(f / self.aspect, 0.0, 0.0, 0.0),
+                    Vector4::new(0.0, f, 0.0, 0.0),
+                    Vector4::new(0.0, 0.0, -1.0, -1.0),
+                    Vector4::new(0.0, 0.0, -2.0 * self.near, 0.0),
+                )
+            }
+            Projection::Orthographic { height } => {
+                let (h, w) = (height / 2.0, height * self.aspect / 2.0);
+                cgmath::ortho(-w, w, -h, h, self.near, self.far)
+            }
+        }
+    }
+
+    /// The camera `t` of the way from `self` to `other`.
+    pub fn lerp(&self, other: &Camera, t: f32) -> Camera {
+        let mut camera = other.clone();
+        camera.pos = self.pos + (other.pos - self.pos) * t;
+        camera.yaw = self.yaw + (other.yaw - self.yaw) * t;
+        camera.pitch = self.pitch + (other.pitch - self.pitch) * t;
+        camera.fov = self.fov + (other.fov - self.fov) * t;
+        if let (Some(from), Some(to)) = (self.free, other.free) {
+            // slerp does not take the shorter way round by itself
+            let from = if from.dot(to) < 0.0 { -from } else { from };
+            camera.free = Some(from.slerp(to, t));
+        }
+        // keeps the camera on the sphere around the target
+        if let (Some(from), Some(to)) = (self.orbit, other.orbit) {
+            camera.orbit = Some(Orbit {
+                target: from.target + (to.target - from.target) * t,
+                distance: from.distance + (to.distance - from.distance) * t,
+            });
+        }
+        camera.update_vectors();
+        camera
     }
 
-    #[allow(dead_code)]
     pub fn pos(&self) -> Point3<f32> {
         self.pos
     }
+
+    pub fn front(&self) -> Vector3<f32> {
+        self.front
+    }
+
+    pub fn yaw(&self) -> f32 {
+        self.yaw
+    }
+
+    pub fn pitch(&self) -> f32 {
+        self.pitch
+    }
+
+    pub fn fov(&self) -> f32 {
+        self.fov
+    }
 }
 
 pub struct CameraBuilder {
@@ -132,23 +342,62 @@ impl CameraBuilder {
             pitch: 0.0,
             aspect: 4.0 / 3.0,
             fov: 45.0,
-            movement: [0.0, 0.0, 0.0, 0.0],
+            fov_range: (1.0, 45.0),
+            height_range: (0.1, 1000.0),
+            near: 0.1,
+            far: 100.0,
+            projection: Projection::Perspective,
+            movement: [0.0; 6],
+            sprinting: false,
+            free: None,
+            orbit: None,
         };
         CameraBuilder { camera }
     }
 
-    #[allow(dead_code)]
     pub fn pitch(mut self, pitch: f32) -> CameraBuilder {
         self.camera.pitch = pitch;
         self
     }
 
-    #[allow(dead_code)]
     pub fn yaw(mut self, yaw: f32) -> CameraBuilder {
         self.camera.yaw = yaw;
         self
     }
 
+    pub fn fov(mut self, fov: f32) -> CameraBuilder {
+        self.camera.fov = fov;
+        self
+    }
+
+    /// Limits of the field of view when zooming, in degrees.
+    pub fn fov_range(mut self, min: f32, max: f32) -> CameraBuilder {
+        self.camera.fov_range = (min, max);
+        self
+    }
+
+    /// Limits of the orthographic view height when zooming.
+    pub fn height_range(mut self, min: f32, max: f32) -> CameraBuilder {
+        self.camera.height_range = (min, max);
+        self
+    }
+
+    pub fn near(mut self, near: f32) -> CameraBuilder {
+        self.camera.near = near;
+        self
+    }
+
+    /// Ignored by `Projection::InfinitePerspective`.
+    pub fn far(mut self, far: f32) -> CameraBuilder {
+        self.camera.far = far;
+        self
+    }
+
+    pub fn projection(mut self, projection: Projection) -> CameraBuilder {
+        self.camera.projection = projection;
+        self
+    }
+
     pub fn aspect(mut self, width: f32, height: f32) -> CameraBuilder {
         self.camera.aspect = width / height;
         self
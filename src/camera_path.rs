@@ -0,0 +1,252 @@
+//! Recorded camera moves, played back along Catmull-Rom splines through the
+//! recorded samples.
+
+use std::error::Error;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::path::Path;
+use cgmath::Point3;
+use toml;
+use camera::Camera;
+
+#[derive(Debug)]
+pub enum CameraPathError {
+    Io(io::Error),
+    Parse(toml::de::Error),
+    Serialize(toml::ser::Error),
+    Invalid(String),
+}
+
+impl fmt::Display for CameraPathError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            CameraPathError::Io(ref e) => write!(f, "cannot access camera path: {}", e),
+            CameraPathError::Parse(ref e) => write!(f, "malformed camera path: {}", e),
+            CameraPathError::Serialize(ref e) => write!(f, "cannot write camera path: {}", e),
+            CameraPathError::Invalid(ref msg) => write!(f, "invalid camera path: {}", msg),
+        }
+    }
+}
+
+impl Error for CameraPathError {}
+
+impl From<io::Error> for CameraPathError {
+    fn from(e: io::Error) -> CameraPathError {
+        CameraPathError::Io(e)
+    }
+}
+
+impl From<toml::de::Error> for CameraPathError {
+    fn from(e: toml::de::Error) -> CameraPathError {
+        CameraPathError::Parse(e)
+    }
+}
+
+impl From<toml::ser::Error> for CameraPathError {
+    fn from(e: toml::ser::Error) -> CameraPathError {
+        CameraPathError::Serialize(e)
+    }
+}
+
+/// Where the camera was and where it looked `time` seconds into the path.
+#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
+pub struct CameraSample {
+    pub time: f32,
+    pub position: [f32; 3],
+    pub yaw: f32,
+    pub pitch: f32,
+    pub fov: f32,
+}
+
+impl CameraSample {
+    /// Puts `camera` at this sample, leaving 6-DoF and orbit mode.
+    pub fn apply(&self, camera: &mut Camera) {
+        let p = self.position;
+        camera.set_view(Point3::new(p[0], p[1], p[2]), self.yaw, self.pitch, self.fov);
+    }
+}
+
+/// Samples sorted by time, at least one once validated.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CameraPath {
+    #[serde(rename = "sample", default)]
+    samples: Vec<CameraSample>,
+}
+
+fn catmull_rom(p0: f32, p1: f32, p2: f32, p3: f32, t: f32) -> f32 {
+    let (t2, t3) = (t * t, t * t * t);
+    0.5 * (2.0 * p1 + (p2 - p0) * t + (2.0 * p0 - 5.0 * p1 + 4.0 * p2 - p3) * t2
+        + (3.0 * p1 - p0 - 3.0 * p2 + p3) * t3)
+}
+
+impl CameraPath {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<CameraPath, CameraPathError> {
+        let mut text = String::new();
+        File::open(path)?.read_to_string(&mut text)?;
+        CameraPath::parse(&text)
+    }
+
+    pub fn parse(text: &str) -> Result<CameraPath, CameraPathError> {
+        let path: CameraPath = toml::from_str(text)?;
+        path.validate()?;
+        Ok(path)
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), CameraPathError> {
+        let text = toml::to_string(self)?;
+        File::create(path)?.write_all(text.as_bytes())?;
+        Ok(())
+    }
+
+    fn validate(&self) -> Result<(), CameraPathError> {
+        if self.samples.is_empty() {
+            return Err(CameraPathError::Invalid("no samples".to_string()));
+        }
+        for (i, s) in self.samples.iter().enumerate() {
+            let values = [s.time, s.position[0], s.position[1], s.position[2], s.yaw, s.pitch, s.fov];
+            if values.iter().any(|v| !v.is_finite()) {
+                return Err(CameraPathError::Invalid(format!("sample {} is not finite", i)));
+            }
+            if i > 0 && s.time <= self.samples[i - 1].time {
+                return Err(CameraPathError::Invalid(
+                    format!("sample {} is not later than the one before", i),
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Appends where `camera` is at `time`, which must be later than the
+    /// last sample. Yaw is unwrapped so that playback turns the short way.
+    pub fn record(&mut self, time: f32, camera: &Camera) {
+        let pos = camera.pos();
+        let mut yaw = camera.yaw();
+        if let Some(last) = self.samples.last() {
+            yaw = last.yaw + (yaw - last.yaw + 180.0).rem_euclid(360.0) - 180.0;
+        }
+        self.samples.push(CameraSample {
+            time,
+            position: [pos.x, pos.y, pos.z],
+            yaw,
+            pitch: camera.pitch(),
+            fov: camera.fov(),
+        });
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.samples.is_empty()
+    }
+
+    /// Time of the last sample.
+    pub fn duration(&self) -> f32 {
+        self.samples.last().map_or(0.0, |s| s.time)
+    }
+
+    /// The camera at `time`, clamped to the recorded range. The path must
+    /// not be empty.
+    pub fn sample(&self, time: f32) -> CameraSample {
+        let samples = &self.samples;
+        let last = samples.len() - 1;
+        let i = samples.iter().rposition(|s| s.time <= time).unwrap_or(0);
+        if i == last || time <= samples[0].time {
+            let mut sample = samples[i];
+            sample.time = time;
+            return sample;
+        }
+        let (p0, p1) = (&samples[i.saturating_sub(1)], &samples[i]);
+        let (p2, p3) = (&samples[i + 1], &samples[(i + 2).min(last)]);
+        let t = (time - p1.time) / (p2.time - p1.time);
+        let spline = |f: &dyn Fn(&CameraSample) -> f32| catmull_rom(f(p0), f(p1), f(p2), f(p3), t);
+        CameraSample {
+            time,
+            position: [
+                spline(&|s| s.position[0]),
+                spline(&|s| s.position[1]),
+                spline(&|s| s.position[2]),
+            ],
+            yaw: spline(&|s| s.yaw),
+            pitch: spline(&|s| s.pitch),
+            fov: spline(&|s| s.fov),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::{Point3, Vector3};
+    use camera::CameraBuilder;
+    use super::*;
+
+    fn sample(time: f32, x: f32) -> CameraSample {
+        CameraSample {
+            time,
+            position: [x, 0.0, 0.0],
+            yaw: -90.0,
+            pitch: 0.0,
+            fov: 45.0,
+        }
+    }
+
+    fn path(samples: Vec<CameraSample>) -> CameraPath {
+        CameraPath { samples }
+    }
+
+    #[test]
+    fn rejects_invalid_samples() {
+        assert!(path(Vec::new()).validate().is_err());
+        assert!(path(vec![sample(0.0, f32::NAN)]).validate().is_err());
+        assert!(path(vec![sample(0.0, 0.0), sample(0.0, 1.0)]).validate().is_err());
+        assert!(path(vec![sample(1.0, 0.0), sample(0.5, 1.0)]).validate().is_err());
+        assert!(path(vec![sample(0.0, 0.0), sample(0.5, 1.0)]).validate().is_ok());
+        match CameraPath::parse("") {
+            Err(CameraPathError::Invalid(_)) => {}
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn sampling_clamps_to_the_ends() {
+        let path = path(vec![sample(1.0, 0.0), sample(2.0, 1.0), sample(3.0, 5.0)]);
+        let before = path.sample(0.0);
+        assert_eq!((before.time, before.position[0]), (0.0, 0.0));
+        let after = path.sample(4.0);
+        assert_eq!((after.time, after.position[0]), (4.0, 5.0));
+        assert_eq!(path.sample(3.0).position[0], 5.0);
+        assert_eq!(path.duration(), 3.0);
+    }
+
+    #[test]
+    fn spline_passes_through_samples() {
+        // evenly spaced points on a line stay on it between the samples,
+        // except next to the ends, where the end sample stands in for the
+        // missing neighbor
+        let line: Vec<_> = (0..5).map(|i| sample(i as f32, i as f32 * 2.0)).collect();
+        let line = path(line);
+        for &t in &[0.0, 1.0, 1.5, 2.0, 2.75, 3.0, 4.0] {
+            let x = line.sample(t).position[0];
+            assert!((x - t * 2.0).abs() < 1e-5, "x({}) is {}", t, x);
+        }
+        // the segment between the second and third sample is used
+        let bend = vec![sample(0.0, 0.0), sample(1.0, 0.0), sample(2.0, 1.0), sample(3.0, 1.0)];
+        let bend = path(bend);
+        let x = bend.sample(1.5).position[0];
+        assert!((x - 0.5).abs() < 1e-5, "x(1.5) is {}", x);
+        assert_eq!(bend.sample(2.0).position[0], 1.0);
+    }
+
+    #[test]
+    fn recording_unwraps_yaw() {
+        let mut path = CameraPath::default();
+        let camera = |yaw| {
+            CameraBuilder::new(Point3::new(0.0, 0.0, 0.0), Vector3::unit_y())
+                .yaw(yaw)
+                .build()
+        };
+        path.record(0.0, &camera(170.0));
+        path.record(1.0, &camera(-170.0));
+        path.record(2.0, &camera(-10.0));
+        let yaws: Vec<f32> = path.samples.iter().map(|s| s.yaw).collect();
+        assert_eq!(yaws, vec![170.0, 190.0, 350.0]);
+    }
+}
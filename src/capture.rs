@@ -0,0 +1,112 @@
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use gfx;
+use gfx::format::Formatted;
+use gfx::handle::RenderTargetView;
+use gfx::traits::FactoryExt;
+use gfx::memory::Typed;
+use image;
+use render::ColorFormat;
+
+#[derive(Debug)]
+pub enum CaptureError {
+    Gfx(String),
+    Io(io::Error),
+}
+
+impl fmt::Display for CaptureError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            CaptureError::Gfx(ref msg) => write!(f, "cannot read back the frame: {}", msg),
+            CaptureError::Io(ref e) => write!(f, "cannot write the image: {}", e),
+        }
+    }
+}
+
+impl Error for CaptureError {}
+
+impl From<io::Error> for CaptureError {
+    fn from(e: io::Error) -> CaptureError {
+        CaptureError::Io(e)
+    }
+}
+
+/// Flushes `encoder` and copies what has been rendered to `target` into an
+/// image, top row first.
+pub fn read_target<D, F, R, C>(
+    device: &mut D,
+    factory: &mut F,
+    encoder: &mut gfx::Encoder<R, C>,
+    target: &RenderTargetView<R, ColorFormat>,
+) -> Result<image::RgbaImage, CaptureError>
+where
+    D: gfx::Device<Resources = R, CommandBuffer = C>,
+    F: gfx::Factory<R>,
+    R: gfx::Resources,
+    C: gfx::CommandBuffer<R>,
+{
+    let (width, height, _, _) = target.get_dimensions();
+    let buffer = factory
+        .create_download_buffer::<[u8; 4]>(width as usize * height as usize)
+        .map_err(|e| CaptureError::Gfx(format!("{:?}", e)))?;
+    let info = gfx::texture::RawImageInfo {
+        xoffset: 0,
+        yoffset: 0,
+        zoffset: 0,
+        width,
+        height,
+        depth: 1,
+        format: ColorFormat::get_format(),
+        mipmap: 0,
+    };
+    encoder
+        .copy_texture_to_buffer_raw(target.raw().get_texture(), None, info, buffer.raw(), 0)
+        .map_err(|e| CaptureError::Gfx(format!("{:?}", e)))?;
+    encoder.flush(device);
+
+    let reader = factory
+        .read_mapping(&buffer)
+        .map_err(|e| CaptureError::Gfx(format!("{:?}", e)))?;
+    // GL rows start at the bottom
+    let row = width as usize;
+    let mut data = Vec::with_capacity(reader.len() * 4);
+    for y in (0..height as usize).rev() {
+        for texel in &reader[y * row..(y + 1) * row] {
+            data.extend_from_slice(texel);
+        }
+    }
+    Ok(image::RgbaImage::from_raw(width as u32, height as u32, data).unwrap())
+}
+
+/// Reads back `target` and writes it to `path` as a PNG.
+pub fn save_png<D, F, R, C, P>(
+    device: &mut D,
+    factory: &mut F,
+    encoder: &mut gfx::Encoder<R, C>,
+    target: &RenderTargetView<R, ColorFormat>,
+    path: P,
+) -> Result<(), CaptureError>
+where
+    D: gfx::Device<Resources = R, CommandBuffer = C>,
+    F: gfx::Factory<R>,
+    R: gfx::Resources,
+    C: gfx::CommandBuffer<R>,
+    P: AsRef<Path>,
+{
+    let img = read_target(device, factory, encoder, target)?;
+    img.save(path)?;
+    Ok(())
+}
+
+/// A `screenshot_<seconds>_<millis>.png` file name in the current directory.
+pub fn screenshot_path() -> PathBuf {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
+    PathBuf::from(format!(
+        "screenshot_{}_{:03}.png",
+        now.as_secs(),
+        now.subsec_millis()
+    ))
+}
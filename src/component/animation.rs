@@ -0,0 +1,157 @@
+use cgmath::prelude::*;
+use cgmath::{Deg, Matrix4, Quaternion, Vector3};
+
+/// A pose at a point in time.
+#[derive(Debug, Copy, Clone)]
+pub struct Keyframe {
+    /// Seconds from the start of the track.
+    pub time: f32,
+    pub translation: Vector3<f32>,
+    pub rotation: Quaternion<f32>,
+    pub scale: f32,
+    /// Diffuse and specular color of a light on the entity.
+    pub color: Option<Vector3<f32>>,
+}
+
+/// What drives an animated entity.
+#[derive(Debug, Clone)]
+pub enum Motion {
+    /// Poses interpolated between keyframes, sorted by time; the track
+    /// loops after the last keyframe.
+    Keyframes(Vec<Keyframe>),
+    /// Circles `center` around `axis` at `radius`.
+    Orbit {
+        center: Vector3<f32>,
+        axis: Vector3<f32>,
+        radius: f32,
+        /// Angular speed in degrees per second.
+        speed: f32,
+        /// Angle at time 0 in degrees.
+        phase: f32,
+    },
+}
+
+/// Poses an entity's `Transform` over time, and the color of its light if
+/// the keyframes have one.
+#[derive(Debug, Clone)]
+pub struct Animation {
+    pub motion: Motion,
+    /// Applied before the animated pose, usually the entity's own rotation
+    /// and scale.
+    pub base: Matrix4<f32>,
+    /// Seconds since the animation started.
+    pub time: f32,
+}
+
+/// A sampled pose.
+pub struct Pose {
+    pub model: Matrix4<f32>,
+    pub color: Option<Vector3<f32>>,
+}
+
+impl Animation {
+    pub fn new(motion: Motion, base: Matrix4<f32>) -> Animation {
+        Animation {
+            motion,
+            base,
+            time: 0.0,
+        }
+    }
+
+    /// The pose at the current time.
+    pub fn sample(&self) -> Pose {
+        match self.motion {
+            Motion::Keyframes(ref keys) => {
+                let pose = sample_keyframes(keys, self.time);
+                Pose {
+                    model: pose.model * self.base,
+                    color: pose.color,
+                }
+            }
+            Motion::Orbit {
+                center,
+                axis,
+                radius,
+                speed,
+                phase,
+            } => {
+                let axis = axis.normalize();
+                // any direction perpendicular to the axis will do as the start
+                let helper = if axis.x.abs() < 0.9 {
+                    Vector3::unit_x()
+                } else {
+                    Vector3::unit_y()
+                };
+                let start = axis.cross(helper).normalize() * radius;
+                let angle = Deg(phase + speed * self.time);
+                let rotation = Quaternion::from_axis_angle(axis, angle);
+                Pose {
+                    model: Matrix4::from_translation(center + rotation.rotate_vector(start)) *
+                        self.base,
+                    color: None,
+                }
+            }
+        }
+    }
+}
+
+fn sample_keyframes(keys: &[Keyframe], time: f32) -> Pose {
+    let last = keys[keys.len() - 1];
+    let duration = last.time - keys[0].time;
+    let t = if duration > 0.0 {
+        keys[0].time + (time % duration)
+    } else {
+        keys[0].time
+    };
+    let next = keys.iter().position(|k| k.time > t).unwrap_or(keys.len() - 1);
+    let (a, b) = if next == 0 {
+        (keys[0], keys[0])
+    } else {
+        (keys[next - 1], keys[next])
+    };
+    let s = if b.time > a.time {
+        ((t - a.time) / (b.time - a.time)).clamp(0.0, 1.0)
+    } else {
+        0.0
+    };
+
+    let translation = a.translation.lerp(b.translation, s);
+    // slerp does not take the shorter way round by itself
+    let to = if a.rotation.dot(b.rotation) < 0.0 { -b.rotation } else { b.rotation };
+    let rotation = a.rotation.slerp(to, s);
+    let scale = a.scale + (b.scale - a.scale) * s;
+    let color = match (a.color, b.color) {
+        (Some(ca), Some(cb)) => Some(ca.lerp(cb, s)),
+        (ca, cb) => ca.or(cb),
+    };
+    Pose {
+        model: Matrix4::from_translation(translation) * Matrix4::from(rotation) *
+            Matrix4::from_scale(scale),
+        color,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::Vector4;
+    use super::*;
+
+    fn key(time: f32, angle: f32) -> Keyframe {
+        Keyframe {
+            time,
+            translation: Vector3::zero(),
+            rotation: Quaternion::from_axis_angle(Vector3::unit_y(), Deg(angle)),
+            scale: 1.0,
+            color: None,
+        }
+    }
+
+    #[test]
+    fn keyframe_rotation_takes_the_shorter_way() {
+        // 170 and -170 degrees are 20 degrees apart through 180, but their
+        // quaternions point away from each other
+        let keys = [key(0.0, 170.0), key(1.0, -170.0)];
+        let x = sample_keyframes(&keys, 0.5).model * Vector4::unit_x();
+        assert!((x.x + 1.0).abs() < 1e-4, "rotated the long way to {:?}", x);
+    }
+}
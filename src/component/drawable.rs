@@ -1,3 +1,18 @@
-pub struct Drawable {
-    mesh: Vec<Vertex>,
-}
\ No newline at end of file
+use std::rc::Rc;
+use cgmath::Vector3;
+use gfx;
+use render::{Material, Mesh};
+
+/// A mesh drawn at the entity's `Transform`.
+pub enum Drawable<R: gfx::Resources> {
+    /// Lit by the scene lights.
+    Object {
+        mesh: Rc<Mesh<R>>,
+        material: Material<R>,
+    },
+    /// Drawn unlit in a flat color, to show where a light is.
+    Lamp {
+        mesh: Rc<Mesh<R>>,
+        color: Vector3<f32>,
+    },
+}
@@ -0,0 +1,10 @@
+use render::{DirLight, PointLight, SpotLight};
+
+/// A light caster. Point and spot lights are moved to the entity's
+/// `Transform` when there is one.
+#[derive(Debug, Copy, Clone)]
+pub enum Light {
+    Directional(DirLight),
+    Point(PointLight),
+    Spot(SpotLight),
+}
@@ -1 +1,9 @@
-pub mod drawable;
\ No newline at end of file
+pub mod animation;
+pub mod drawable;
+pub mod light;
+pub mod transform;
+
+pub use self::animation::{Animation, Keyframe, Motion};
+pub use self::drawable::Drawable;
+pub use self::light::Light;
+pub use self::transform::Transform;
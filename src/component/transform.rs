@@ -0,0 +1,23 @@
+use cgmath::{Matrix4, Vector3};
+
+/// Places an entity in the world.
+#[derive(Debug, Copy, Clone)]
+pub struct Transform {
+    pub model: Matrix4<f32>,
+}
+
+impl Transform {
+    pub fn new(model: Matrix4<f32>) -> Transform {
+        Transform { model }
+    }
+
+    pub fn position(&self) -> Vector3<f32> {
+        self.model.w.truncate()
+    }
+
+    /// Blends the matrices element-wise, which is close enough to a proper
+    /// interpolation for the small changes between two ticks.
+    pub fn lerp(&self, other: &Transform, t: f32) -> Transform {
+        Transform::new(self.model + (other.model - self.model) * t)
+    }
+}
@@ -1,9 +1,12 @@
 use std::collections::HashSet;
 use cgmath::{Point2, Vector2};
-use glutin::{GlWindow, VirtualKeyCode};
+use glutin::{GlContext, GlWindow, HeadlessContext, MouseButton, VirtualKeyCode};
 use gfx::handle::{DepthStencilView, RenderTargetView};
 use gfx_device_gl::Resources as R;
+use gfx_window_glutin;
+use input::{Action, Bindings, InputEvent};
 use render;
+use system::DispatcherRequest;
 
 
 pub struct KeyState {
@@ -32,6 +35,7 @@ pub struct MouseState {
     center: Point2<f32>,
     delta: Vector2<f32>,
     scroll: f32,
+    buttons: HashSet<MouseButton>,
 }
 
 impl MouseState {
@@ -40,6 +44,7 @@ impl MouseState {
             center: Point2::new(0.0, 0.0),
             delta: Vector2::new(0.0, 0.0),
             scroll: 0.0,
+            buttons: HashSet::new(),
         }
     }
 
@@ -70,44 +75,164 @@ impl MouseState {
     pub fn delta(&self) -> Vector2<f32> {
         self.delta
     }
+
+    pub fn update_button(&mut self, button: MouseButton, pressed: bool) {
+        if pressed {
+            self.buttons.insert(button);
+        } else {
+            self.buttons.remove(&button);
+        }
+    }
+
+    pub fn is_pressed(&self, button: MouseButton) -> bool {
+        self.buttons.contains(&button)
+    }
 }
 
-pub struct Context {
-    pub window: GlWindow,
+/// What the GL context renders to.
+#[allow(clippy::large_enum_variant)]
+pub enum Surface {
+    Window(GlWindow),
+    /// An offscreen context, presented by reading back the render target.
+    /// Only held so that the GL context outlives the device.
+    #[allow(dead_code)]
+    Headless(HeadlessContext),
+}
+
+impl Surface {
+    pub fn window(&self) -> Option<&GlWindow> {
+        match *self {
+            Surface::Window(ref window) => Some(window),
+            Surface::Headless(_) => None,
+        }
+    }
+
+    pub fn swap_buffers(&self) {
+        if let Surface::Window(ref window) = *self {
+            window.swap_buffers().unwrap();
+        }
+    }
+}
+
+/// The state driven by `InputEvent`s: held keys and buttons, the mouse and
+/// what the actions bound to them asked for. Holds nothing of the window, so
+/// that input can be fed without one.
+pub struct InputState {
     pub key_state: KeyState,
+    pub bindings: Bindings,
     pub mouse_state: MouseState,
     pub screen_width: i32,
     pub screen_height: i32,
     mouse_reset: bool,
-    pub render_target: RenderTargetView<R, render::ColorFormat>,
-    pub depth_stencil: DepthStencilView<R, render::DepthFormat>,
     pub running: bool,
+    /// Set to save the current frame once it has been drawn.
+    pub screenshot_requested: bool,
+    /// Handled by the `Dispatcher` before the next frame.
+    pub dispatcher_requests: Vec<DispatcherRequest>,
+    /// Whether to draw the debug text overlay.
+    pub overlay: bool,
+    /// One-shot actions pressed since the last update tick, for the systems
+    /// handling them to take with `take_press`.
+    presses: Vec<Action>,
 }
 
-impl Context {
-    pub fn new(
-        window: GlWindow,
-        screen_width: i32,
-        screen_height: i32,
-        render_target: RenderTargetView<R, render::ColorFormat>,
-        depth_stencil: DepthStencilView<R, render::DepthFormat>,
-    ) -> Context {
-        let mut ctx = Context {
-            window,
+impl InputState {
+    pub fn new(screen_width: i32, screen_height: i32) -> InputState {
+        let mut input = InputState {
             key_state: KeyState::new(),
+            bindings: Bindings::default(),
             mouse_state: MouseState::new(),
             screen_width,
             screen_height,
             mouse_reset: true,
-            render_target,
-            depth_stencil,
             running: true,
+            screenshot_requested: false,
+            dispatcher_requests: Vec::new(),
+            overlay: true,
+            presses: Vec::new(),
         };
-        ctx.reset_mouse_pos();
-        ctx
+        input.reset_mouse_pos();
+        input
+    }
+
+    /// Updates the input state from `event` and handles the actions that
+    /// happen once per key press.
+    pub fn handle(&mut self, event: InputEvent) {
+        match event {
+            InputEvent::Close => self.running = false,
+            InputEvent::Key(key, pressed) => {
+                // key repeat sends presses without releases in between
+                let was_pressed = self.key_state.is_pressed(key);
+                self.key_state.update_key(key, pressed);
+                if pressed && !was_pressed {
+                    for action in self.bindings.actions(key) {
+                        self.trigger(action);
+                    }
+                }
+            }
+            InputEvent::MouseMove(x, y) => self.update_mouse_pos(x, y),
+            InputEvent::MouseButton(button, pressed) => {
+                self.mouse_state.update_button(button, pressed)
+            }
+            InputEvent::Scroll(dy) => self.mouse_state.update_scroll(dy),
+            InputEvent::Resize(w, h) => self.update_dimensions(w, h),
+            InputEvent::Focus => self.focused(),
+            InputEvent::MouseEnter => {}
+        }
+    }
+
+    fn trigger(&mut self, action: Action) {
+        match action {
+            Action::Quit => self.running = false,
+            Action::Screenshot => self.screenshot_requested = true,
+            Action::ToggleOverlay => self.overlay = !self.overlay,
+            Action::ToggleFlashlight => {
+                self.dispatcher_requests.push(DispatcherRequest::Toggle("flashlight"))
+            }
+            Action::ReportTimings => self.dispatcher_requests.push(DispatcherRequest::ReportTimings),
+            Action::ReloadBindings => {
+                match self.bindings.reload() {
+                    Ok(()) => println!("> key bindings reloaded"),
+                    Err(e) => eprintln!("{}", e),
+                }
+            }
+            Action::ToggleDirLights
+            | Action::TogglePointLights
+            | Action::ToggleSpotLights
+            | Action::SelectNextLight
+            | Action::ToggleFreeLook
+            | Action::ToggleOrbit
+            | Action::RecordCamera
+            | Action::PlayCamera => self.presses.push(action),
+            _ => {}
+        }
     }
 
-    pub fn update_mouse_pos(&mut self, x: i32, y: i32) {
+    /// Takes one press of `action` queued since the last update tick.
+    pub fn take_press(&mut self, action: Action) -> bool {
+        match self.presses.iter().position(|&a| a == action) {
+            Some(i) => {
+                self.presses.remove(i);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Drops the presses no system took, e.g. because it is disabled.
+    pub fn clear_presses(&mut self) {
+        self.presses.clear();
+    }
+
+    /// Whether any key bound to `action` is held down.
+    pub fn is_active(&self, action: Action) -> bool {
+        self.bindings
+            .keys(action)
+            .iter()
+            .any(|&key| self.key_state.is_pressed(key))
+    }
+
+    fn update_mouse_pos(&mut self, x: i32, y: i32) {
         if self.mouse_reset {
             if x == self.screen_width / 2 && y == self.screen_height / 2 {
                 self.mouse_reset = false;
@@ -119,9 +244,6 @@ impl Context {
         } else {
             self.mouse_state.update_position(x, y);
         }
-        self.window
-            .set_cursor_position(self.screen_width / 2, self.screen_height / 2)
-            .unwrap();
     }
 
     pub fn reset_mouse_pos(&mut self) {
@@ -131,7 +253,7 @@ impl Context {
         );
     }
 
-    pub fn update_dimensions(&mut self, width: u32, height: u32) {
+    fn update_dimensions(&mut self, width: u32, height: u32) {
         self.screen_width = width as i32;
         self.screen_height = height as i32;
         self.mouse_state.update_center(
@@ -141,14 +263,138 @@ impl Context {
         println!("> resized: {} x {}", width, height);
     }
 
-    pub fn focused(&mut self) {
+    fn focused(&mut self) {
         self.mouse_reset = true;
         println!("> mouse will be reset");
     }
+}
+
+pub struct Context {
+    pub surface: Surface,
+    pub input: InputState,
+    pub render_target: RenderTargetView<R, render::ColorFormat>,
+    pub depth_stencil: DepthStencilView<R, render::DepthFormat>,
+    /// Frame rate measured by the game loop.
+    pub fps: f32,
+    /// Set by a system that cannot go on; the program then stops with an
+    /// error.
+    pub failed: bool,
+}
+
+impl Context {
+    pub fn new(
+        surface: Surface,
+        screen_width: i32,
+        screen_height: i32,
+        render_target: RenderTargetView<R, render::ColorFormat>,
+        depth_stencil: DepthStencilView<R, render::DepthFormat>,
+    ) -> Context {
+        Context {
+            surface,
+            input: InputState::new(screen_width, screen_height),
+            render_target,
+            depth_stencil,
+            fps: 0.0,
+            failed: false,
+        }
+    }
+
+    /// Feeds `event` to the input state and keeps the window in step: the
+    /// views follow its size and the cursor stays centered.
+    pub fn handle(&mut self, event: InputEvent) {
+        self.input.handle(event);
+        match event {
+            InputEvent::Resize(..) => {
+                if let Surface::Window(ref window) = self.surface {
+                    gfx_window_glutin::update_views(
+                        window,
+                        &mut self.render_target,
+                        &mut self.depth_stencil,
+                    );
+                }
+            }
+            InputEvent::MouseMove(..) | InputEvent::MouseEnter => self.center_cursor(),
+            _ => {}
+        }
+    }
+
+    fn center_cursor(&self) {
+        if let Some(window) = self.surface.window() {
+            window
+                .set_cursor_position(self.input.screen_width / 2, self.input.screen_height / 2)
+                .unwrap();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glutin::VirtualKeyCode as Key;
+    use super::*;
+
+    #[test]
+    fn key_presses_trigger_actions_once() {
+        let mut input = InputState::new(800, 600);
+        input.handle(InputEvent::Key(Key::F2, true));
+        // key repeat
+        input.handle(InputEvent::Key(Key::F2, true));
+        input.handle(InputEvent::Key(Key::F3, true));
+        assert_eq!(
+            input.dispatcher_requests,
+            vec![DispatcherRequest::Toggle("flashlight"), DispatcherRequest::ReportTimings]
+        );
+        input.handle(InputEvent::Key(Key::F1, true));
+        assert!(!input.overlay);
+        input.handle(InputEvent::Key(Key::F12, true));
+        assert!(input.screenshot_requested);
+        input.handle(InputEvent::Key(Key::Escape, true));
+        assert!(!input.running);
+    }
+
+    #[test]
+    fn quick_taps_are_queued() {
+        let mut input = InputState::new(800, 600);
+        // two taps within one tick
+        for &pressed in &[true, false, true, false] {
+            input.handle(InputEvent::Key(Key::Tab, pressed));
+        }
+        input.handle(InputEvent::Key(Key::Key2, true));
+        assert!(input.take_press(Action::TogglePointLights));
+        assert!(input.take_press(Action::SelectNextLight));
+        assert!(input.take_press(Action::SelectNextLight));
+        assert!(!input.take_press(Action::SelectNextLight));
+        // still held, not pressed again
+        input.handle(InputEvent::Key(Key::Key2, true));
+        assert!(!input.take_press(Action::TogglePointLights));
+        input.handle(InputEvent::Key(Key::Key1, true));
+        input.clear_presses();
+        assert!(!input.take_press(Action::ToggleDirLights));
+    }
+
+    #[test]
+    fn held_keys_activate_actions() {
+        let mut input = InputState::new(800, 600);
+        input.handle(InputEvent::Key(Key::W, true));
+        input.handle(InputEvent::Key(Key::LShift, true));
+        assert!(input.is_active(Action::MoveForward));
+        // bound to both
+        assert!(input.is_active(Action::MoveDown) && input.is_active(Action::Pan));
+        input.handle(InputEvent::Key(Key::W, false));
+        assert!(!input.is_active(Action::MoveForward));
+        assert!(input.dispatcher_requests.is_empty());
+    }
 
-    pub fn mouse_entered(&mut self) {
-        self.window
-            .set_cursor_position(self.screen_width / 2, self.screen_height / 2)
-            .unwrap();
+    #[test]
+    fn mouse_moves_after_reset() {
+        let mut input = InputState::new(800, 600);
+        input.handle(InputEvent::Resize(800, 600));
+        // ignored until the cursor has been centered
+        input.handle(InputEvent::MouseMove(500, 300));
+        assert_eq!(input.mouse_state.delta(), Vector2::new(0.0, 0.0));
+        input.handle(InputEvent::MouseMove(400, 300));
+        input.handle(InputEvent::MouseMove(410, 295));
+        assert_eq!(input.mouse_state.delta(), Vector2::new(10.0, -5.0));
+        input.handle(InputEvent::Close);
+        assert!(!input.running);
     }
 }
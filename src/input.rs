@@ -0,0 +1,357 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use glutin::{ElementState, MouseButton, MouseScrollDelta, WindowEvent};
+use glutin::VirtualKeyCode as Key;
+use toml;
+
+/// Something the user can do with the keyboard.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Deserialize)]
+pub enum Action {
+    MoveForward,
+    MoveBackward,
+    StrafeLeft,
+    StrafeRight,
+    MoveUp,
+    MoveDown,
+    /// Held to move faster.
+    Sprint,
+    RollLeft,
+    RollRight,
+    /// Switches the camera between yaw/pitch and 6-DoF mode.
+    ToggleFreeLook,
+    /// Switches the camera between flying and orbiting a target.
+    ToggleOrbit,
+    /// Held to pan instead of rotate while dragging in orbit mode.
+    Pan,
+    /// Starts recording the camera path, or stops and saves it.
+    RecordCamera,
+    /// Plays the loaded or last recorded camera path, or stops playing it.
+    PlayCamera,
+    Quit,
+    Screenshot,
+    ToggleOverlay,
+    ToggleFlashlight,
+    ReportTimings,
+    ReloadBindings,
+    ToggleDirLights,
+    TogglePointLights,
+    ToggleSpotLights,
+    SelectNextLight,
+    LightLeft,
+    LightRight,
+    LightUp,
+    LightDown,
+    LightForward,
+    LightBack,
+    LightRed,
+    LightGreen,
+    LightBlue,
+    LightConstant,
+    LightLinear,
+    LightQuadratic,
+    /// Held to lower instead of raise the edited light parameters.
+    LightDecrease,
+}
+
+const DEFAULT_BINDINGS: &[(Action, &[Key])] = &[
+    (Action::MoveForward, &[Key::W]),
+    (Action::MoveBackward, &[Key::S]),
+    (Action::StrafeLeft, &[Key::A]),
+    (Action::StrafeRight, &[Key::D]),
+    (Action::MoveUp, &[Key::Space]),
+    (Action::MoveDown, &[Key::LShift]),
+    (Action::Sprint, &[Key::LControl]),
+    (Action::RollLeft, &[Key::Q]),
+    (Action::RollRight, &[Key::E]),
+    (Action::ToggleFreeLook, &[Key::F4]),
+    (Action::ToggleOrbit, &[Key::F6]),
+    (Action::Pan, &[Key::LShift]),
+    (Action::RecordCamera, &[Key::F7]),
+    (Action::PlayCamera, &[Key::F8]),
+    (Action::Quit, &[Key::Escape]),
+    (Action::Screenshot, &[Key::F12]),
+    (Action::ToggleOverlay, &[Key::F1]),
+    (Action::ToggleFlashlight, &[Key::F2]),
+    (Action::ReportTimings, &[Key::F3]),
+    (Action::ReloadBindings, &[Key::F5]),
+    (Action::ToggleDirLights, &[Key::Key1]),
+    (Action::TogglePointLights, &[Key::Key2]),
+    (Action::ToggleSpotLights, &[Key::Key3]),
+    (Action::SelectNextLight, &[Key::Tab]),
+    (Action::LightLeft, &[Key::Left]),
+    (Action::LightRight, &[Key::Right]),
+    (Action::LightUp, &[Key::PageUp]),
+    (Action::LightDown, &[Key::PageDown]),
+    (Action::LightForward, &[Key::Up]),
+    (Action::LightBack, &[Key::Down]),
+    (Action::LightRed, &[Key::R]),
+    (Action::LightGreen, &[Key::G]),
+    (Action::LightBlue, &[Key::B]),
+    (Action::LightConstant, &[Key::J]),
+    (Action::LightLinear, &[Key::K]),
+    (Action::LightQuadratic, &[Key::L]),
+    (Action::LightDecrease, &[Key::LAlt]),
+];
+
+macro_rules! key_names {
+    ($($key:ident),*) => {
+        &[$((stringify!($key), Key::$key)),*]
+    }
+}
+
+/// Names of the keys that can be bound, as spelled in `VirtualKeyCode`.
+const KEY_NAMES: &[(&str, Key)] = key_names![
+    Key1, Key2, Key3, Key4, Key5, Key6, Key7, Key8, Key9, Key0,
+    A, B, C, D, E, F, G, H, I, J, K, L, M, N, O, P, Q, R, S, T, U, V, W, X, Y, Z,
+    Escape, F1, F2, F3, F4, F5, F6, F7, F8, F9, F10, F11, F12, F13, F14, F15,
+    Snapshot, Scroll, Pause, Insert, Home, Delete, End, PageDown, PageUp,
+    Left, Up, Right, Down, Back, Return, Space, Compose, Numlock,
+    Numpad0, Numpad1, Numpad2, Numpad3, Numpad4, Numpad5, Numpad6, Numpad7, Numpad8, Numpad9,
+    Add, Apostrophe, Backslash, Capital, Colon, Comma, Decimal, Divide, Equals, Grave,
+    LAlt, LBracket, LControl, LShift, LWin, Minus, Multiply, NumpadComma, NumpadEnter,
+    NumpadEquals, Period, RAlt, RBracket, RControl, RShift, RWin, Semicolon, Slash,
+    Subtract, Tab
+];
+
+fn key_from_name(name: &str) -> Option<Key> {
+    KEY_NAMES.iter().find(|&&(n, _)| n == name).map(|&(_, key)| key)
+}
+
+fn key_name(key: Key) -> Option<&'static str> {
+    KEY_NAMES.iter().find(|&&(_, k)| k == key).map(|&(name, _)| name)
+}
+
+/// Window input the program reacts to, independent of where it came from,
+/// so that it can be recorded, replayed or made up.
+///
+/// Written one per line as e.g. `key W down`, `move 512 384`,
+/// `button Left up`, `scroll -1`, `resize 800 600`, `focus`, `enter` or
+/// `close`.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum InputEvent {
+    Key(Key, bool),
+    MouseMove(i32, i32),
+    MouseButton(MouseButton, bool),
+    Scroll(f32),
+    Resize(u32, u32),
+    Focus,
+    MouseEnter,
+    Close,
+}
+
+impl InputEvent {
+    /// The input carried by a glutin window event, if it is one the program
+    /// uses and can be written down.
+    pub fn from_glutin(event: &WindowEvent) -> Option<InputEvent> {
+        let pressed = |state| state == ElementState::Pressed;
+        match *event {
+            WindowEvent::Closed => Some(InputEvent::Close),
+            WindowEvent::KeyboardInput { input, .. } => input
+                .virtual_keycode
+                .filter(|&key| key_name(key).is_some())
+                .map(|key| InputEvent::Key(key, pressed(input.state))),
+            WindowEvent::MouseMoved { position: (x, y), .. } => {
+                Some(InputEvent::MouseMove(x as i32, y as i32))
+            }
+            WindowEvent::MouseInput { state, button, .. } => {
+                Some(InputEvent::MouseButton(button, pressed(state)))
+            }
+            WindowEvent::MouseWheel { delta: MouseScrollDelta::LineDelta(_, dy), .. } => {
+                Some(InputEvent::Scroll(dy))
+            }
+            WindowEvent::Resized(w, h) => Some(InputEvent::Resize(w, h)),
+            WindowEvent::Focused(true) => Some(InputEvent::Focus),
+            WindowEvent::MouseEntered { .. } => Some(InputEvent::MouseEnter),
+            _ => None,
+        }
+    }
+}
+
+fn up_down(pressed: bool) -> &'static str {
+    if pressed { "down" } else { "up" }
+}
+
+impl fmt::Display for InputEvent {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            InputEvent::Key(key, pressed) => {
+                write!(f, "key {} {}", key_name(key).unwrap_or("?"), up_down(pressed))
+            }
+            InputEvent::MouseMove(x, y) => write!(f, "move {} {}", x, y),
+            InputEvent::MouseButton(button, pressed) => {
+                let name = match button {
+                    MouseButton::Left => "Left".to_string(),
+                    MouseButton::Right => "Right".to_string(),
+                    MouseButton::Middle => "Middle".to_string(),
+                    MouseButton::Other(n) => n.to_string(),
+                };
+                write!(f, "button {} {}", name, up_down(pressed))
+            }
+            InputEvent::Scroll(dy) => write!(f, "scroll {}", dy),
+            InputEvent::Resize(w, h) => write!(f, "resize {} {}", w, h),
+            InputEvent::Focus => write!(f, "focus"),
+            InputEvent::MouseEnter => write!(f, "enter"),
+            InputEvent::Close => write!(f, "close"),
+        }
+    }
+}
+
+impl FromStr for InputEvent {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<InputEvent, String> {
+        fn number<T: FromStr>(words: &[&str], i: usize) -> Result<T, String> {
+            words
+                .get(i)
+                .and_then(|w| w.parse().ok())
+                .ok_or_else(|| format!("expected a number in \"{}\"", words.join(" ")))
+        }
+        let words: Vec<&str> = s.split_whitespace().collect();
+        let state = |i: usize| match words.get(i) {
+            Some(&"down") => Ok(true),
+            Some(&"up") => Ok(false),
+            _ => Err(format!("expected \"up\" or \"down\" in \"{}\"", s)),
+        };
+        let event = match words.first() {
+            Some(&"key") => {
+                let name = words.get(1).cloned().unwrap_or("");
+                let key = key_from_name(name).ok_or_else(|| format!("unknown key \"{}\"", name))?;
+                InputEvent::Key(key, state(2)?)
+            }
+            Some(&"move") => InputEvent::MouseMove(number(&words, 1)?, number(&words, 2)?),
+            Some(&"button") => {
+                let button = match words.get(1) {
+                    Some(&"Left") => MouseButton::Left,
+                    Some(&"Right") => MouseButton::Right,
+                    Some(&"Middle") => MouseButton::Middle,
+                    _ => MouseButton::Other(number(&words, 1)?),
+                };
+                InputEvent::MouseButton(button, state(2)?)
+            }
+            Some(&"scroll") => InputEvent::Scroll(number(&words, 1)?),
+            Some(&"resize") => InputEvent::Resize(number(&words, 1)?, number(&words, 2)?),
+            Some(&"focus") => InputEvent::Focus,
+            Some(&"enter") => InputEvent::MouseEnter,
+            Some(&"close") => InputEvent::Close,
+            _ => return Err(format!("unknown input event \"{}\"", s)),
+        };
+        Ok(event)
+    }
+}
+
+#[derive(Debug)]
+pub enum BindingsError {
+    Io(io::Error),
+    Parse(toml::de::Error),
+    UnknownAction(String),
+    UnknownKey(String),
+}
+
+impl fmt::Display for BindingsError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            BindingsError::Io(ref e) => write!(f, "cannot read key bindings: {}", e),
+            BindingsError::Parse(ref e) => write!(f, "malformed key bindings: {}", e),
+            BindingsError::UnknownAction(ref name) => write!(f, "unknown action \"{}\"", name),
+            BindingsError::UnknownKey(ref name) => write!(f, "unknown key \"{}\"", name),
+        }
+    }
+}
+
+impl Error for BindingsError {}
+
+impl From<io::Error> for BindingsError {
+    fn from(e: io::Error) -> BindingsError {
+        BindingsError::Io(e)
+    }
+}
+
+impl From<toml::de::Error> for BindingsError {
+    fn from(e: toml::de::Error) -> BindingsError {
+        BindingsError::Parse(e)
+    }
+}
+
+/// Maps actions to the keys that trigger them. An action can have several
+/// keys and a key can trigger several actions.
+#[derive(Debug, Clone)]
+pub struct Bindings {
+    keys: HashMap<Action, Vec<Key>>,
+    /// File the bindings were loaded from, for `reload`.
+    path: Option<PathBuf>,
+}
+
+impl Default for Bindings {
+    fn default() -> Bindings {
+        Bindings {
+            keys: DEFAULT_BINDINGS
+                .iter()
+                .map(|&(action, keys)| (action, keys.to_vec()))
+                .collect(),
+            path: None,
+        }
+    }
+}
+
+impl Bindings {
+    /// The defaults, overridden by the actions listed in the TOML file at
+    /// `path`, e.g. `MoveForward = ["W", "Up"]`.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Bindings, BindingsError> {
+        let mut text = String::new();
+        File::open(path.as_ref())?.read_to_string(&mut text)?;
+        let mut bindings = Bindings::parse(&text)?;
+        bindings.path = Some(path.as_ref().to_path_buf());
+        Ok(bindings)
+    }
+
+    pub fn parse(text: &str) -> Result<Bindings, BindingsError> {
+        let table: HashMap<String, Vec<String>> = toml::from_str(text)?;
+        let mut bindings = Bindings::default();
+        for (name, names) in table {
+            // the toml deserializer only takes strings as table keys
+            let action = toml::Value::String(name.clone())
+                .try_into()
+                .map_err(|_| BindingsError::UnknownAction(name))?;
+            let keys = names
+                .iter()
+                .map(|name| {
+                    key_from_name(name).ok_or_else(|| BindingsError::UnknownKey(name.clone()))
+                })
+                .collect::<Result<_, _>>()?;
+            bindings.bind(action, keys);
+        }
+        Ok(bindings)
+    }
+
+    /// Reads the bindings file again, keeping the current bindings if it
+    /// fails. Does nothing for bindings not loaded from a file.
+    pub fn reload(&mut self) -> Result<(), BindingsError> {
+        if let Some(path) = self.path.clone() {
+            *self = Bindings::load(path)?;
+        }
+        Ok(())
+    }
+
+    /// Replaces the keys of `action`; no keys unbinds it.
+    pub fn bind(&mut self, action: Action, keys: Vec<Key>) {
+        self.keys.insert(action, keys);
+    }
+
+    pub fn keys(&self, action: Action) -> &[Key] {
+        self.keys.get(&action).map_or(&[], |keys| keys.as_slice())
+    }
+
+    /// Every action `key` triggers.
+    pub fn actions(&self, key: Key) -> Vec<Action> {
+        self.keys
+            .iter()
+            .filter(|&(_, keys)| keys.contains(&key))
+            .map(|(&action, _)| action)
+            .collect()
+    }
+}
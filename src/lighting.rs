@@ -0,0 +1,493 @@
+//! CPU mirror of the Phong lighting in `shader/fragment.glsl`.
+//!
+//! Every function follows its GLSL counterpart line by line, including the
+//! `vec4` arithmetic, so the lighting math can be tested without a GPU. When
+//! the shader changes, change this module with it; the tests below fail
+//! until both agree.
+
+use cgmath::prelude::*;
+use cgmath::{Matrix4, Vector3, Vector4};
+use render::{DirLight, Lights, PointLight, SpotLight, MAX_DIR_LIGHTS};
+
+/// What the fragment shader knows about the fragment being lit.
+#[derive(Debug, Copy, Clone)]
+pub struct Fragment {
+    pub pos: Vector3<f32>,
+    pub normal: Vector3<f32>,
+    /// `texture(material_diffuse, TexCoords)`
+    pub diffuse: Vector4<f32>,
+    /// `texture(material_specular, TexCoords)`
+    pub specular: Vector4<f32>,
+    pub shininess: f32,
+}
+
+/// One layer of `shadowMap` with its `lightSpace` matrix.
+#[derive(Debug, Clone)]
+pub struct ShadowMap {
+    pub size: usize,
+    /// Depth as seen from the light, row by row from the bottom.
+    pub depths: Vec<f32>,
+    pub light_space: Matrix4<f32>,
+}
+
+impl ShadowMap {
+    /// `texture(shadowMap, ...).r` with nearest filtering and a border of 1.
+    fn texture(&self, u: f32, v: f32) -> f32 {
+        if !(0.0..=1.0).contains(&u) || !(0.0..=1.0).contains(&v) {
+            return 1.0;
+        }
+        let texel = |t: f32| ((t * self.size as f32) as usize).min(self.size - 1);
+        self.depths[texel(v) * self.size + texel(u)]
+    }
+}
+
+fn reflect(i: Vector4<f32>, n: Vector4<f32>) -> Vector4<f32> {
+    i - n * (2.0 * n.dot(i))
+}
+
+fn phong(
+    ambient: Vector4<f32>,
+    diffuse: Vector4<f32>,
+    specular: Vector4<f32>,
+    light_dir: Vector4<f32>,
+    normal: Vector4<f32>,
+    view_dir: Vector4<f32>,
+    frag: &Fragment,
+) -> (Vector4<f32>, Vector4<f32>, Vector4<f32>) {
+    // diffuse shading
+    let diff = normal.dot(light_dir).max(0.0);
+    // specular shading
+    let reflect_dir = reflect(-light_dir, normal);
+    let spec = view_dir.dot(reflect_dir).max(0.0).powf(frag.shininess);
+    (
+        ambient.mul_element_wise(frag.diffuse),
+        (diffuse * diff).mul_element_wise(frag.diffuse),
+        (specular * spec).mul_element_wise(frag.specular),
+    )
+}
+
+fn attenuation(a0: f32, a1: f32, a2: f32, distance: f32) -> f32 {
+    1.0 / (a0 + a1 * distance + a2 * (distance * distance))
+}
+
+pub fn shadow_pcf(map: &ShadowMap, frag_pos_light_space: Vector4<f32>, bias: f32) -> f32 {
+    let p = frag_pos_light_space.truncate() / frag_pos_light_space.w * 0.5
+        + Vector3::new(0.5, 0.5, 0.5);
+    // beyond the far plane of the light
+    if p.z > 1.0 {
+        return 0.0;
+    }
+    let texel_size = 1.0 / map.size as f32;
+    let mut shadow = 0.0;
+    for x in -1..2 {
+        for y in -1..2 {
+            let closest = map.texture(p.x + x as f32 * texel_size, p.y + y as f32 * texel_size);
+            shadow += if p.z - bias > closest { 1.0 } else { 0.0 };
+        }
+    }
+    shadow / 9.0
+}
+
+pub fn calc_dir_light(
+    light: &DirLight,
+    normal: Vector4<f32>,
+    view_dir: Vector4<f32>,
+    shadow: f32,
+    frag: &Fragment,
+) -> Vector4<f32> {
+    let light_dir = (-Vector4::from(light.dir)).normalize();
+    let (ambient, diffuse, specular) = phong(
+        light.ambient.into(),
+        light.diffuse.into(),
+        light.specular.into(),
+        light_dir,
+        normal,
+        view_dir,
+        frag,
+    );
+    ambient + (diffuse + specular) * (1.0 - shadow)
+}
+
+pub fn calc_point_light(
+    light: &PointLight,
+    normal: Vector4<f32>,
+    frag_pos: Vector4<f32>,
+    view_dir: Vector4<f32>,
+    frag: &Fragment,
+) -> Vector4<f32> {
+    let to_light = Vector4::from(light.pos) - frag_pos;
+    let (ambient, diffuse, specular) = phong(
+        light.ambient.into(),
+        light.diffuse.into(),
+        light.specular.into(),
+        to_light.normalize(),
+        normal,
+        view_dir,
+        frag,
+    );
+    let attenuation = attenuation(light.a0, light.a1, light.a2, to_light.magnitude());
+    (ambient + diffuse + specular) * attenuation
+}
+
+pub fn calc_spot_light(
+    light: &SpotLight,
+    normal: Vector4<f32>,
+    frag_pos: Vector4<f32>,
+    view_dir: Vector4<f32>,
+    frag: &Fragment,
+) -> Vector4<f32> {
+    let to_light = Vector4::from(light.pos) - frag_pos;
+    let light_dir = to_light.normalize();
+    let (ambient, diffuse, specular) = phong(
+        light.ambient.into(),
+        light.diffuse.into(),
+        light.specular.into(),
+        light_dir,
+        normal,
+        view_dir,
+        frag,
+    );
+    let attenuation = attenuation(light.a0, light.a1, light.a2, to_light.magnitude());
+    // soft edge between the inner and outer cone
+    let theta = light_dir.dot((-Vector4::from(light.dir)).normalize());
+    let epsilon = light.cut_off - light.outer_cut_off;
+    let intensity = ((theta - light.outer_cut_off) / epsilon).clamp(0.0, 1.0);
+    ambient * attenuation + (diffuse + specular) * (attenuation * intensity)
+}
+
+/// The shader's `main` without shadow maps.
+pub fn shade(lights: &Lights, view_pos: Vector3<f32>, frag: &Fragment) -> Vector4<f32> {
+    shade_with_shadows(lights, &[], 0.0, view_pos, frag)
+}
+
+/// The shader's `main`: the sum of every enabled light, the first
+/// directional lights shadowed by `shadows`.
+pub fn shade_with_shadows(
+    lights: &Lights,
+    shadows: &[ShadowMap],
+    shadow_bias: f32,
+    view_pos: Vector3<f32>,
+    frag: &Fragment,
+) -> Vector4<f32> {
+    assert!(shadows.len() <= MAX_DIR_LIGHTS);
+    let args = lights.args();
+    let norm = frag.normal.normalize().extend(0.0);
+    let view_dir = (view_pos - frag.pos).normalize().extend(0.0);
+    let frag_pos = frag.pos.extend(1.0);
+
+    let mut result = Vector4::zero();
+    for (i, light) in lights.dir()[..args.num_dir as usize].iter().enumerate() {
+        let shadow = match shadows.get(i) {
+            Some(map) => {
+                // surfaces at a grazing angle to the light need more bias
+                let cosine = norm.dot((-Vector4::from(light.dir)).normalize());
+                let bias = (10.0 * shadow_bias * (1.0 - cosine)).max(shadow_bias);
+                shadow_pcf(map, map.light_space * frag_pos, bias)
+            }
+            None => 0.0,
+        };
+        result += calc_dir_light(light, norm, view_dir, shadow, frag);
+    }
+    for light in &lights.point()[..args.num_point as usize] {
+        result += calc_point_light(light, norm, frag_pos, view_dir, frag);
+    }
+    for light in &lights.spot()[..args.num_spot as usize] {
+        result += calc_spot_light(light, norm, frag_pos, view_dir, frag);
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cgmath::Deg;
+    use render::LightKind;
+
+    const EPSILON: f32 = 1e-5;
+
+    fn assert_close(a: Vector4<f32>, b: Vector4<f32>) {
+        assert!(
+            (a - b).magnitude() < EPSILON,
+            "{:?} is not close to {:?}",
+            a,
+            b
+        );
+    }
+
+    /// A white, upward facing fragment at the origin.
+    fn fragment() -> Fragment {
+        Fragment {
+            pos: Vector3::zero(),
+            normal: Vector3::unit_y(),
+            diffuse: Vector4::new(1.0, 1.0, 1.0, 1.0),
+            specular: Vector4::new(1.0, 1.0, 1.0, 1.0),
+            shininess: 32.0,
+        }
+    }
+
+    fn gray(v: f32) -> Vector3<f32> {
+        Vector3::new(v, v, v)
+    }
+
+    fn rgba(v: f32, w: f32) -> Vector4<f32> {
+        Vector4::new(v, v, v, w)
+    }
+
+    fn sun(dir: Vector3<f32>) -> DirLight {
+        DirLight::new(gray(0.1), gray(0.5), gray(1.0), dir)
+    }
+
+    #[test]
+    fn dir_light_head_on() {
+        let mut lights = Lights::new();
+        lights.add_dir(sun(-Vector3::unit_y())).unwrap();
+        let color = shade(&lights, Vector3::unit_y(), &fragment());
+        // full diffuse, and the reflection points straight at the viewer
+        assert_close(color, rgba(0.1 + 0.5 + 1.0, 3.0));
+    }
+
+    #[test]
+    fn dir_light_from_below_only_ambient() {
+        let mut lights = Lights::new();
+        lights.add_dir(sun(Vector3::unit_y())).unwrap();
+        let view_pos = Vector3::new(1.0, 1.0, 0.0);
+        let color = shade(&lights, view_pos, &fragment());
+        assert_close(color, rgba(0.1, 1.0));
+    }
+
+    #[test]
+    fn dir_lights_accumulate() {
+        let mut one = Lights::new();
+        one.add_dir(sun(-Vector3::unit_y())).unwrap();
+        let mut two = one.clone();
+        two.add_dir(sun(Vector3::new(-1.0, -1.0, 0.0))).unwrap();
+
+        let view_pos = Vector3::unit_y();
+        let frag = fragment();
+        let first = shade(&one, view_pos, &frag);
+        let second = calc_dir_light(
+            &two.dir()[1],
+            Vector3::unit_y().extend(0.0),
+            Vector3::unit_y().extend(0.0),
+            0.0,
+            &frag,
+        );
+        assert_close(shade(&two, view_pos, &frag), first + second);
+    }
+
+    #[test]
+    fn point_light_attenuation() {
+        let d = 3.0;
+        let light = PointLight::new(gray(0.0), gray(1.0), gray(0.0), Vector3::new(0.0, d, 0.0))
+            .attenuation(1.0, 0.5, 0.25);
+        let mut lights = Lights::new();
+        lights.add_point(light).unwrap();
+        // grazing view, so the specular term is exactly zero
+        let color = shade(&lights, Vector3::unit_x(), &fragment());
+        let expected = 1.0 / (1.0 + 0.5 * d + 0.25 * d * d);
+        // w carries the attenuated alpha of the ambient and diffuse terms
+        assert_close(color, Vector4::new(expected, expected, expected, 2.0 * expected));
+    }
+
+    /// An unattenuated spot light right above the fragment.
+    fn flashlight(dir: Vector3<f32>) -> SpotLight {
+        let mut light = SpotLight::new(
+            gray(0.2),
+            gray(1.0),
+            gray(0.0),
+            Vector3::unit_y(),
+            dir,
+            Deg(10.0),
+            Deg(20.0),
+        );
+        light.a1 = 0.0;
+        light.a2 = 0.0;
+        light
+    }
+
+    #[test]
+    fn spot_light_cone() {
+        // grazing view, so the specular term is exactly zero
+        let view_pos = Vector3::unit_x();
+        let frag = fragment();
+        let mut lights = Lights::new();
+
+        // straight down: inside the inner cone
+        lights.add_spot(flashlight(-Vector3::unit_y())).unwrap();
+        assert_close(shade(&lights, view_pos, &frag), Vector4::new(1.2, 1.2, 1.2, 2.0));
+
+        // 45 degrees off: outside the outer cone, only ambient is left
+        let mut lights = Lights::new();
+        lights.add_spot(flashlight(Vector3::new(1.0, -1.0, 0.0))).unwrap();
+        assert_close(shade(&lights, view_pos, &frag), Vector4::new(0.2, 0.2, 0.2, 1.0));
+
+        // 15 degrees off: part way through the soft edge
+        let mut lights = Lights::new();
+        let dir = Vector3::new(Deg(15.0).sin(), -Deg(15.0).cos(), 0.0);
+        lights.add_spot(flashlight(dir)).unwrap();
+        let intensity = (Deg(15.0).cos() - Deg(20.0).cos()) / (Deg(10.0).cos() - Deg(20.0).cos());
+        let color = shade(&lights, view_pos, &frag);
+        assert_close(
+            color,
+            Vector4::new(0.2 + intensity, 0.2 + intensity, 0.2 + intensity, 1.0 + intensity),
+        );
+    }
+
+    #[test]
+    fn disabled_lights_do_not_contribute() {
+        let mut lights = Lights::new();
+        lights.add_dir(sun(-Vector3::unit_y())).unwrap();
+        lights.add_point(PointLight::new(gray(0.1), gray(1.0), gray(1.0), Vector3::unit_y()))
+            .unwrap();
+        lights.set_enabled(LightKind::Directional, false);
+        lights.set_enabled(LightKind::Point, false);
+        assert_close(shade(&lights, Vector3::unit_y(), &fragment()), Vector4::zero());
+    }
+
+    /// A 4x4 shadow map seen through the identity matrix, so the fragment at
+    /// the origin lands on the corner of the middle texels at depth 0.5.
+    fn shadow_map(depth: impl Fn(usize, usize) -> f32) -> ShadowMap {
+        let size = 4;
+        let depths = (0..size * size).map(|i| depth(i % size, i / size)).collect();
+        ShadowMap {
+            size,
+            depths,
+            light_space: Matrix4::identity(),
+        }
+    }
+
+    #[test]
+    fn dir_light_in_shadow_only_ambient() {
+        let mut lights = Lights::new();
+        lights.add_dir(sun(-Vector3::unit_y())).unwrap();
+        let occluded = shadow_map(|_, _| 0.0);
+        let color = shade_with_shadows(&lights, &[occluded], 0.005, Vector3::unit_y(), &fragment());
+        assert_close(color, rgba(0.1, 1.0));
+    }
+
+    #[test]
+    fn shadow_bias_prevents_acne() {
+        let mut lights = Lights::new();
+        lights.add_dir(sun(-Vector3::unit_y())).unwrap();
+        // the fragment itself is the closest surface to the light
+        let map = shadow_map(|_, _| 0.5);
+        let view_pos = Vector3::unit_y();
+        let lit = shade(&lights, view_pos, &fragment());
+        assert_close(shade_with_shadows(&lights, &[map], 0.005, view_pos, &fragment()), lit);
+        // without a bias rounding can put it behind itself
+        let map = shadow_map(|_, _| 0.5 - 1e-4);
+        let acne = shade_with_shadows(&lights, &[map], 0.0, view_pos, &fragment());
+        assert_close(acne, rgba(0.1, 1.0));
+    }
+
+    #[test]
+    fn pcf_softens_shadow_edges() {
+        // an occluder covers the left half of the map; the filter reaches
+        // one column into it
+        let map = shadow_map(|x, _| if x < 2 { 0.0 } else { 1.0 });
+        let shadow = shadow_pcf(&map, Vector4::new(0.0, 0.0, 0.0, 1.0), 0.005);
+        assert!((shadow - 3.0 / 9.0).abs() < EPSILON, "shadow is {}", shadow);
+        // beyond the light's far plane nothing is shadowed
+        let behind = shadow_pcf(&map, Vector4::new(0.0, 0.0, 1.5, 1.0), 0.005);
+        assert_eq!(behind, 0.0);
+    }
+
+    const SHADER: &str = include_str!("shader/fragment.glsl");
+
+    /// Returns the source of a GLSL function with whitespace collapsed.
+    fn glsl_function(name: &str) -> String {
+        let start = SHADER
+            .find(&format!("{}(", name))
+            .unwrap_or_else(|| panic!("{} not found in fragment.glsl", name));
+        let start = SHADER[..start].rfind('\n').map_or(0, |i| i + 1);
+        let end = start + SHADER[start..].find("\n}").expect("unterminated function") + 2;
+        SHADER[start..end]
+            .lines()
+            .map(|line| line.split("//").next().unwrap())
+            .flat_map(|line| line.split_whitespace())
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    #[test]
+    fn every_light_class_accumulates() {
+        let main = glsl_function("void main");
+        for call in &["CalcDirLight", "CalcPointLight", "CalcSpotLight"] {
+            assert!(
+                main.contains(&format!("result += {}(", call)),
+                "fragment.glsl must add {} to the result",
+                call
+            );
+        }
+    }
+
+    /// Guards the mirror: a change to the math of the shader's lighting
+    /// functions must be made to this module too, then the copy below
+    /// updated. Comments and whitespace are ignored.
+    #[test]
+    fn shader_matches_mirror() {
+        let expected = [
+            "float ShadowPCF(int layer, vec4 fragPosLightSpace, float bias) { \
+             vec3 projCoords = fragPosLightSpace.xyz / fragPosLightSpace.w * 0.5 + 0.5; \
+             if (projCoords.z > 1.0) return 0.0; \
+             vec2 texelSize = 1.0 / vec2(textureSize(shadowMap, 0).xy); \
+             float shadow = 0.0; \
+             for (int x = -1; x <= 1; x++) for (int y = -1; y <= 1; y++) { \
+             vec2 uv = projCoords.xy + vec2(x, y) * texelSize; \
+             float closest = texture(shadowMap, vec3(uv, layer)).r; \
+             shadow += projCoords.z - bias > closest ? 1.0 : 0.0; } \
+             return shadow / 9.0; }",
+            "vec4 CalcDirLight(DirLight light, vec4 normal, vec4 viewDir, float shadow) { \
+             vec4 lightDir = normalize(-light.dir); \
+             float diff = max(dot(normal, lightDir), 0.0); \
+             vec4 reflectDir = reflect(-lightDir, normal); \
+             float spec = pow(max(dot(viewDir, reflectDir), 0.0), material_shininess); \
+             vec4 ambient = light.ambient * texture(material_diffuse, TexCoords); \
+             vec4 diffuse = light.diffuse * diff * texture(material_diffuse, TexCoords); \
+             vec4 specular = light.specular * spec * texture(material_specular, TexCoords); \
+             return (ambient + (1.0 - shadow) * (diffuse + specular)); }",
+            "vec4 CalcPointLight(PointLight light, vec4 normal, vec4 fragPos, vec4 viewDir) { \
+             vec4 lightDir = normalize(light.pos - fragPos); \
+             float diff = max(dot(normal, lightDir), 0.0); \
+             vec4 reflectDir = reflect(-lightDir, normal); \
+             float spec = pow(max(dot(viewDir, reflectDir), 0.0), material_shininess); \
+             float distance = length(light.pos - fragPos); \
+             float attenuation = 1.0 / (light.a0 + light.a1 * distance + light.a2 * (distance * distance)); \
+             vec4 ambient = light.ambient * texture(material_diffuse, TexCoords); \
+             vec4 diffuse = light.diffuse * diff * texture(material_diffuse, TexCoords); \
+             vec4 specular = light.specular * spec * texture(material_specular, TexCoords); \
+             ambient *= attenuation; diffuse *= attenuation; specular *= attenuation; \
+             return (ambient + diffuse + specular); }",
+            "vec4 CalcSpotLight(SpotLight light, vec4 normal, vec4 fragPos, vec4 viewDir) { \
+             vec4 lightDir = normalize(light.pos - fragPos); \
+             float diff = max(dot(normal, lightDir), 0.0); \
+             vec4 reflectDir = reflect(-lightDir, normal); \
+             float spec = pow(max(dot(viewDir, reflectDir), 0.0), material_shininess); \
+             float distance = length(light.pos - fragPos); \
+             float attenuation = 1.0 / (light.a0 + light.a1 * distance + light.a2 * (distance * distance)); \
+             float theta = dot(lightDir, normalize(-light.dir)); \
+             float epsilon = light.cut_off - light.outer_cut_off; \
+             float intensity = clamp((theta - light.outer_cut_off) / epsilon, 0.0, 1.0); \
+             vec4 ambient = light.ambient * texture(material_diffuse, TexCoords); \
+             vec4 diffuse = light.diffuse * diff * texture(material_diffuse, TexCoords); \
+             vec4 specular = light.specular * spec * texture(material_specular, TexCoords); \
+             ambient *= attenuation; diffuse *= attenuation * intensity; \
+             specular *= attenuation * intensity; \
+             return (ambient + diffuse + specular); }",
+        ];
+        let names = [
+            "float ShadowPCF",
+            "vec4 CalcDirLight",
+            "vec4 CalcPointLight",
+            "vec4 CalcSpotLight",
+        ];
+        for (name, expected) in names.iter().zip(expected.iter()) {
+            assert_eq!(
+                glsl_function(name),
+                *expected,
+                "{} in fragment.glsl changed; update lighting.rs to match",
+                name
+            );
+        }
+    }
+}
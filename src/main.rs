@@ -6,175 +6,301 @@ extern crate gfx_window_glutin;
 extern crate gfx_device_gl;
 extern crate glutin;
 extern crate image;
+extern crate serde;
 #[macro_use]
-extern crate lazy_static;
+extern crate serde_derive;
 extern crate structopt;
 #[macro_use]
 extern crate structopt_derive;
 extern crate spin_sleep;
+extern crate tobj;
+extern crate toml;
 
 //use std::time;
-use gfx::Device;
-use glutin::GlContext;
-use cgmath::{Matrix4, Point3, Vector3, Rad};
+use std::fs;
+use std::process;
+use std::rc::Rc;
+use cgmath::{Deg, Vector3};
 use cgmath::prelude::*;
 use structopt::StructOpt;
 
 mod render;
 mod model;
+#[cfg(test)]
+mod lighting;
+mod obj;
 mod camera;
+mod camera_path;
+mod font;
+mod input;
 mod context;
 mod system;
 mod app;
+mod component;
+mod world;
+mod capture;
+mod scene;
+mod replay;
 
-use system::{CameraSystem, SysEventSystem, System};
-use camera::CameraBuilder;
+use system::{AnimationSystem, CameraSystem, Dispatcher, FlashlightSystem, LightEditSystem,
+             RenderSystem, Stage, SysEventSystem};
 use app::App;
+use camera_path::CameraPath;
+use input::Bindings;
+use render::LightKind;
+use scene::Scene;
+use component::Light;
+use world::World;
+use opt::Opt;
 
 
 const SCREEN_WIDTH: i32 = 1024;
 const SCREEN_HEIGHT: i32 = 768;
+const TITLE: &str = "Learn OpenGL";
 
-#[derive(StructOpt)]
-#[structopt(name = "light-casters", about = "Multiple light casters")]
-struct Opt {
-    #[structopt(long = "directional", help = "Enable directional lights")]
-    dir: Option<bool>,
-    #[structopt(long = "point", help = "Enable point lights")]
-    point: Option<bool>,
-    #[structopt(long = "spot", help = "Enable spot light")]
-    spot: Option<bool>,
+// structopt-derive expands to an impl inside an anonymous const
+#[allow(non_local_definitions)]
+mod opt {
+    #[derive(StructOpt)]
+    #[structopt(name = "light-casters", about = "Multiple light casters")]
+    pub struct Opt {
+        #[structopt(long = "directional", help = "Enable directional lights")]
+        pub dir: Option<bool>,
+        #[structopt(long = "point", help = "Enable point lights")]
+        pub point: Option<bool>,
+        #[structopt(long = "spot", help = "Enable spot light")]
+        pub spot: Option<bool>,
+        #[structopt(long = "flashlight", help = "Attach the spot light to the camera")]
+        pub flashlight: Option<bool>,
+        #[structopt(long = "scene", help = "Load the scene from a TOML file")]
+        pub scene: Option<String>,
+        #[structopt(long = "headless", help = "Render offscreen and save the frames as PNG files")]
+        pub headless: bool,
+        #[structopt(long = "frames", help = "Number of frames to render in headless mode (default 1)")]
+        pub frames: Option<u32>,
+        #[structopt(long = "out", help = "Directory for headless frames (default: current directory)")]
+        pub out: Option<String>,
+        #[structopt(long = "tick-rate", help = "Simulation updates per second (default 60)")]
+        pub tick_rate: Option<f32>,
+        #[structopt(long = "fps", help = "Frame rate limit, 0 for uncapped (default 60)")]
+        pub fps: Option<f64>,
+        #[structopt(long = "vsync", help = "Wait for vertical sync when presenting frames")]
+        pub vsync: Option<bool>,
+        #[structopt(long = "bindings", help = "Load key bindings from a TOML file")]
+        pub bindings: Option<String>,
+        #[structopt(long = "record-camera", help = "File for recorded camera paths (default camera_path.toml)")]
+        pub record_camera: Option<String>,
+        #[structopt(long = "play-camera", help = "Play a recorded camera path from a TOML file")]
+        pub play_camera: Option<String>,
+        #[structopt(long = "record-input", help = "Record the input of the session to a file")]
+        pub record_input: Option<String>,
+        #[structopt(long = "replay-input", help = "Replay the input recorded in a file")]
+        pub replay_input: Option<String>,
+        #[structopt(long = "shadow-size", help = "Shadow map resolution in texels, per directional light (default 2048)")]
+        pub shadow_size: Option<u16>,
+        #[structopt(long = "shadow-bias", help = "Depth bias against shadow acne (default 0.005)")]
+        pub shadow_bias: Option<f32>,
+    }
 }
 
+
 fn main() {
     let opt = Opt::from_args();
 
-    let (mut device, mut factory, events_loop, mut ctx) = App::init("Learn OpenGL", 1024, 768);
-    let mut encoder: gfx::Encoder<_, _> = factory.create_command_buffer().into();
-
-    let material = render::Material::new(
-        &mut factory,
-        "textures/container2.png",
-        "textures/container2_specular.png",
-        32.0,
-    );
-    let rot_axis = Vector3::new(1.0, 0.3, 0.5).normalize();
-    let cubes: Vec<_> = model::cube_positions()
-        .into_iter()
-        .enumerate()
-        .map(|(i, pos)| {
-            render::Object::new(
-                &mut factory,
-                model::vertices(),
-                Matrix4::from_translation(pos) *
-                    Matrix4::from_axis_angle(rot_axis, Rad(20.0 * i as f32)),
-                material.clone(),
-            )
-        })
-        .collect();
+    let frames = opt.frames.unwrap_or(1);
+    let out_dir = opt.out.as_ref().map_or(".", |s| s.as_str());
+    if opt.headless {
+        if frames == 0 {
+            eprintln!("frames must be at least 1");
+            process::exit(1);
+        }
+        if let Err(e) = fs::create_dir_all(out_dir) {
+            eprintln!("{}: {}", out_dir, e);
+            process::exit(1);
+        }
+    }
 
-    let cube_brush = render::ObjectBrush::new(&mut factory);
-    let lamp_brush = render::LampBrush::new(&mut factory);
+    let fps = opt.fps.unwrap_or(60.0);
+    if !fps.is_finite() || fps < 0.0 {
+        eprintln!("fps must be 0 or positive");
+        process::exit(1);
+    }
+    let vsync = opt.vsync == Some(true);
+    let tick_rate = opt.tick_rate.unwrap_or(60.0);
+    if !tick_rate.is_finite() || tick_rate <= 0.0 {
+        eprintln!("tick rate must be positive");
+        process::exit(1);
+    }
 
-    let light_color = Vector3::new(1.0, 1.0, 1.0);
-    let scale = Matrix4::from_scale(0.2);
+    let bindings = match opt.bindings {
+        Some(ref path) => Bindings::load(path).unwrap_or_else(|e| {
+            eprintln!("{}: {}", path, e);
+            process::exit(1);
+        }),
+        None => Bindings::default(),
+    };
 
-    let dir_lights: Vec<_> = model::light_directions()
-        .into_iter()
-        .map(|dir| {
-            render::DirLight::new((light_color * 0.05), (light_color * 0.3), light_color, dir)
+    if opt.record_input.is_some() && opt.replay_input.is_some() {
+        eprintln!("cannot record and replay input at the same time");
+        process::exit(1);
+    }
+    let replay = opt.replay_input.as_ref().map(|path| {
+        replay::load(path).unwrap_or_else(|e| {
+            eprintln!("{}: {}", path, e);
+            process::exit(1);
         })
-        .collect();
-
-    let point_lights: Vec<_> = model::light_positions()
-        .into_iter()
-        .map(|pos| {
-            render::PointLight::new(light_color * 0.05, light_color * 0.3, light_color, pos)
+    });
+    // the events are fed by `SysEventSystem` like live ones, the frame
+    // times by the game loop
+    let mut replay_times = replay
+        .as_ref()
+        .map(|frames| frames.iter().map(|frame| frame.dt).collect::<Vec<_>>().into_iter());
+    let recorder = opt.record_input.as_ref().map(|path| {
+        replay::InputRecorder::create(path).unwrap_or_else(|e| {
+            eprintln!("{}: {}", path, e);
+            process::exit(1);
         })
-        .collect();
-
-    let light_args = render::LightArgs {
-        num_dir: if let Some(false) = opt.dir {
-            0
-        } else {
-            model::light_directions().len() as i32
-        },
-        num_point: if let Some(false) = opt.point {
-            0
-        } else {
-            model::light_positions().len() as i32
-        },
-    };
+    });
 
-    let lamps: Vec<_> = model::light_positions()
-        .into_iter()
-        .map(|pos| {
-            render::Lamp::new(
-                &mut factory,
-                model::vertices(),
-                Matrix4::from_translation(pos) * scale,
-                light_color,
-            )
+    let camera_path = opt.play_camera.as_ref().map(|path| {
+        CameraPath::load(path).unwrap_or_else(|e| {
+            eprintln!("{}: {}", path, e);
+            process::exit(1);
         })
-        .collect();
+    });
 
-    // Game loop
-    //let start_time = time::Instant::now();
-    let mut loop_helper = spin_sleep::LoopHelper::builder()
-       .report_interval_s(0.5) // report every half a second
-       .build_with_target_rate(60.0); // limit to 60 FPS if possible
+    let shadow_size = opt.shadow_size.unwrap_or(2048);
+    if shadow_size == 0 || shadow_size > 8192 {
+        eprintln!("shadow size must be between 1 and 8192");
+        process::exit(1);
+    }
+    let shadow_bias = opt.shadow_bias.unwrap_or(0.005);
+    if !shadow_bias.is_finite() || shadow_bias < 0.0 {
+        eprintln!("shadow bias must be 0 or positive");
+        process::exit(1);
+    }
 
-    let mut current_fps = None;
+    let scene = match opt.scene {
+        Some(ref path) => Scene::load(path).unwrap_or_else(|e| {
+            eprintln!("{}: {}", path, e);
+            process::exit(1);
+        }),
+        None => Scene::builtin(),
+    };
+
+    let (device, mut factory, events_loop, mut ctx) = if opt.headless {
+        let (device, factory, ctx) = App::init_headless(SCREEN_WIDTH as u32, SCREEN_HEIGHT as u32)
+            .unwrap_or_else(|e| {
+                eprintln!("{}", e);
+                process::exit(1);
+            });
+        (device, factory, None, ctx)
+    } else {
+        let (device, factory, events_loop, ctx) =
+            App::init(TITLE, SCREEN_WIDTH as u32, SCREEN_HEIGHT as u32, vsync);
+        (device, factory, Some(events_loop), ctx)
+    };
 
-    let camera = CameraBuilder::new(Point3::new(0.0, 0.0, 3.0), Vector3::unit_y())
-        .aspect(SCREEN_WIDTH as f32, SCREEN_HEIGHT as f32)
+    ctx.input.bindings = bindings;
+
+    let mut world = World::new();
+    let light_color = Vector3::new(1.0, 1.0, 1.0);
+    let camera = scene.camera(SCREEN_WIDTH as f32, SCREEN_HEIGHT as f32);
+    let flashlight = render::SpotLight::new(
+        Vector3::zero(),
+        light_color,
+        light_color,
+        camera.pos().to_vec(),
+        camera.front(),
+        Deg(12.5),
+        Deg(15.0),
+    );
+    world
+        .create_entity()
+        .with(camera)
+        .with(Light::Spot(flashlight))
         .build();
 
+    // after the flashlight, so that spawning checks it against the light limits too
+    let cube_mesh = Rc::new(render::Mesh::new(&mut factory, &model::vertices()));
+    scene.spawn(&mut world, &mut factory, &cube_mesh).unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        process::exit(1);
+    });
+
+    world.set_light_enabled(LightKind::Directional, opt.dir != Some(false));
+    world.set_light_enabled(LightKind::Point, opt.point != Some(false));
+    world.set_light_enabled(LightKind::Spot, opt.spot != Some(false));
+
+    let mut render_system = RenderSystem::new(device, factory, shadow_size, shadow_bias);
+    if opt.headless {
+        ctx.input.overlay = false;
+        render_system = render_system.write_frames(out_dir, frames);
+    }
 
-    let mut cs = CameraSystem::new(camera, 0.1);
-    let mut es = SysEventSystem::new(events_loop);
+    let mut dispatcher = Dispatcher::new(tick_rate);
+    let mut events = match events_loop {
+        Some(events_loop) => SysEventSystem::new(events_loop),
+        None => SysEventSystem::headless(),
+    };
+    if let Some(recorder) = recorder {
+        events = events.record(recorder);
+    }
+    if let Some(frames) = replay {
+        events = events.replay(frames);
+    }
+    dispatcher.add("events", Stage::Input, events);
+    let mut camera_system = CameraSystem::new(0.1);
+    if let Some(ref file) = opt.record_camera {
+        camera_system = camera_system.record_to(file);
+    }
+    if let Some(path) = camera_path {
+        camera_system = camera_system.play(path);
+    }
+    dispatcher.add("camera", Stage::Update, camera_system);
+    dispatcher.add("animation", Stage::Update, AnimationSystem);
+    dispatcher.add("light_edit", Stage::Update, LightEditSystem);
+    dispatcher.add("flashlight", Stage::Render, FlashlightSystem);
+    dispatcher.set_enabled("flashlight", opt.flashlight != Some(false));
+    dispatcher.add("render", Stage::Render, render_system);
 
-    while ctx.running {
+    // Game loop
+    //let start_time = time::Instant::now();
+    let loop_builder = spin_sleep::LoopHelper::builder().report_interval_s(0.5);
+    let mut loop_helper = if fps > 0.0 {
+        loop_builder.build_with_target_rate(fps)
+    } else {
+        loop_builder.build_without_target_rate()
+    };
+
+    while ctx.input.running && !ctx.failed {
         let delta = loop_helper.loop_start(); // or .loop_start_s() for f64 seconds
         //let elapsed = current_frame.duration_since(start_time);
         //context.events_loop.poll_events(|event| { update(event, &mut context); });
 
-        let dt = delta.as_secs() as f32 + delta.subsec_nanos() as f32 / 1e9;
+        // recorded frame times or one tick per headless frame, so that the
+        // output is reproducible
+        let dt = match replay_times {
+            Some(ref mut times) => match times.next() {
+                Some(dt) => dt,
+                None => break,
+            },
+            None if opt.headless => dispatcher.tick(),
+            None => delta.as_secs() as f32 + delta.subsec_nanos() as f32 / 1e9,
+        };
         //let elapsed = elapsed.as_secs() as f32 + elapsed.subsec_nanos() as f32 / 1e9;
-        es.run(&mut ctx, dt);
-        cs.run(&mut ctx, dt);
-
-        if let Some(fps) = loop_helper.report_rate() {
-            current_fps = Some(fps);
+        dispatcher.run(&mut ctx, &mut world, dt);
+        if !opt.headless {
+            if let Some(rate) = loop_helper.report_rate() {
+                ctx.fps = rate as f32;
+                if let Some(window) = ctx.surface.window() {
+                    window.set_title(&format!("{} - {:.0} FPS", TITLE, rate));
+                }
+            }
+            loop_helper.loop_sleep(); // sleeps to acheive the target rate
         }
-
-        let camera = cs.camera();
-        encoder.clear(&ctx.render_target, render::BG);
-        encoder.clear_depth(&ctx.depth_stencil, 1.0);
-        for cube in cubes.iter() {
-            cube_brush.draw(
-                &cube,
-                &dir_lights,
-                &point_lights,
-                &light_args,
-                camera,
-                &ctx.render_target,
-                &ctx.depth_stencil,
-                &mut encoder,
-            );
-        }
-        for lamp in lamps.iter() {
-            lamp_brush.draw(
-                &lamp,
-                camera,
-                &ctx.render_target,
-                &ctx.depth_stencil,
-                &mut encoder,
-            );
-        }
-        encoder.flush(&mut device);
-        ctx.window.swap_buffers().unwrap();
-        device.cleanup();
-        loop_helper.loop_sleep(); // sleeps to acheive a 60 FPS rate
     }
-}
\ No newline at end of file
+    if ctx.failed {
+        process::exit(1);
+    }
+}
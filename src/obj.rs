@@ -0,0 +1,201 @@
+use std::error::Error;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use cgmath::prelude::*;
+use cgmath::Vector3;
+use gfx;
+use tobj;
+use render::{self, Vertex};
+
+/// How to fill in normals for meshes that do not provide any.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Hash, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Normals {
+    /// One normal per face, giving a faceted look.
+    Flat,
+    /// Area-weighted average of the faces sharing a position.
+    #[default]
+    Smooth,
+}
+
+#[derive(Debug)]
+pub enum ObjError {
+    Load(tobj::LoadError),
+    Material(tobj::LoadError),
+    Texture(render::TextureError),
+}
+
+impl fmt::Display for ObjError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ObjError::Load(ref e) => write!(f, "cannot load OBJ file: {}", e),
+            ObjError::Material(ref e) => write!(f, "cannot load MTL file: {}", e),
+            ObjError::Texture(ref e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl Error for ObjError {}
+
+impl From<render::TextureError> for ObjError {
+    fn from(e: render::TextureError) -> ObjError {
+        ObjError::Texture(e)
+    }
+}
+
+/// The parts of an MTL material that map onto `render::Material`.
+#[derive(Debug, Clone)]
+pub struct ObjMaterial {
+    pub diffuse_texture: Option<PathBuf>,
+    pub specular_texture: Option<PathBuf>,
+    pub diffuse: [f32; 3],
+    pub specular: [f32; 3],
+    pub shininess: f32,
+}
+
+impl ObjMaterial {
+    fn new(mtl: &tobj::Material, base: &Path) -> ObjMaterial {
+        let texture = |name: &str| if name.is_empty() {
+            None
+        } else {
+            Some(base.join(name))
+        };
+        ObjMaterial {
+            diffuse_texture: texture(&mtl.diffuse_texture),
+            specular_texture: texture(&mtl.specular_texture),
+            diffuse: mtl.diffuse,
+            specular: mtl.specular,
+            // `Ns` defaults to 0 when absent, which would make every surface
+            // fully specular.
+            shininess: if mtl.shininess > 0.0 {
+                mtl.shininess
+            } else {
+                32.0
+            },
+        }
+    }
+
+    /// Uploads the material, substituting a 1x1 texture of the `Kd`/`Ks`
+    /// color for a missing `map_Kd`/`map_Ks`.
+    pub fn material<F, R>(&self, factory: &mut F) -> Result<render::Material<R>, ObjError>
+    where
+        F: gfx::Factory<R>,
+        R: gfx::Resources,
+    {
+        let diffuse = match self.diffuse_texture {
+            Some(ref path) => render::load_texture(factory, &path.to_string_lossy())?,
+            None => render::color_texture(factory, self.diffuse),
+        };
+        let specular = match self.specular_texture {
+            Some(ref path) => render::load_texture(factory, &path.to_string_lossy())?,
+            None => render::color_texture(factory, self.specular),
+        };
+        Ok(render::Material {
+            diffuse,
+            specular,
+            shininess: self.shininess,
+        })
+    }
+}
+
+pub struct ObjMesh {
+    pub name: String,
+    pub vertices: Vec<Vertex>,
+    pub material: Option<ObjMaterial>,
+}
+
+/// Loads every model in an OBJ file as a triangle list, together with the
+/// material it references in the accompanying MTL file.
+pub fn load<P: AsRef<Path>>(path: P, normals: Normals) -> Result<Vec<ObjMesh>, ObjError> {
+    let path = path.as_ref();
+    let options = tobj::LoadOptions {
+        triangulate: true,
+        ..Default::default()
+    };
+    let (models, materials) = tobj::load_obj(path, &options).map_err(ObjError::Load)?;
+    let materials = materials.map_err(ObjError::Material)?;
+    let base = path.parent().unwrap_or_else(|| Path::new(""));
+
+    Ok(
+        models
+            .into_iter()
+            .map(|model| {
+                ObjMesh {
+                    vertices: vertices(&model.mesh, normals),
+                    material: model.mesh.material_id.and_then(|id| materials.get(id)).map(
+                        |mtl| ObjMaterial::new(mtl, base),
+                    ),
+                    name: model.name,
+                }
+            })
+            .collect(),
+    )
+}
+
+fn position(mesh: &tobj::Mesh, index: u32) -> Vector3<f32> {
+    let i = index as usize * 3;
+    Vector3::new(mesh.positions[i], mesh.positions[i + 1], mesh.positions[i + 2])
+}
+
+fn face_normal(mesh: &tobj::Mesh, tri: &[u32]) -> Vector3<f32> {
+    let p0 = position(mesh, tri[0]);
+    let p1 = position(mesh, tri[1]);
+    let p2 = position(mesh, tri[2]);
+    // not normalized: its length is twice the triangle area
+    (p1 - p0).cross(p2 - p0)
+}
+
+fn normalize_or_zero(v: Vector3<f32>) -> Vector3<f32> {
+    if v.magnitude2() > 0.0 {
+        v.normalize()
+    } else {
+        v
+    }
+}
+
+fn smooth_normals(mesh: &tobj::Mesh) -> Vec<Vector3<f32>> {
+    let mut normals = vec![Vector3::zero(); mesh.positions.len() / 3];
+    for tri in mesh.indices.chunks(3) {
+        let n = face_normal(mesh, tri);
+        for &i in tri {
+            normals[i as usize] += n;
+        }
+    }
+    normals.into_iter().map(normalize_or_zero).collect()
+}
+
+fn vertices(mesh: &tobj::Mesh, normals: Normals) -> Vec<Vertex> {
+    // faces may omit `vn`/`vt` individually, so indices can be short
+    let missing_normals = mesh.normal_indices.len() < mesh.indices.len();
+    let smooth = if missing_normals && normals == Normals::Smooth {
+        Some(smooth_normals(mesh))
+    } else {
+        None
+    };
+
+    let mut vertices = Vec::with_capacity(mesh.indices.len());
+    for (face, tri) in mesh.indices.chunks(3).enumerate() {
+        let flat = normalize_or_zero(face_normal(mesh, tri));
+        for (k, &pi) in tri.iter().enumerate() {
+            let n = face * 3 + k;
+            let normal = match (mesh.normal_indices.get(n), smooth.as_ref()) {
+                (Some(&ni), _) => {
+                    let i = ni as usize * 3;
+                    [mesh.normals[i], mesh.normals[i + 1], mesh.normals[i + 2]]
+                }
+                (None, Some(smooth)) => smooth[pi as usize].into(),
+                (None, None) => flat.into(),
+            };
+            let uv = match mesh.texcoord_indices.get(n) {
+                Some(&ti) => {
+                    let i = ti as usize * 2;
+                    // OBJ puts v = 0 at the bottom, textures are uploaded top row first
+                    [mesh.texcoords[i], 1.0 - mesh.texcoords[i + 1]]
+                }
+                None => [0.0, 0.0],
+            };
+            vertices.push(Vertex::new(position(mesh, pi).into(), normal, uv));
+        }
+    }
+    vertices
+}
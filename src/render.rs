@@ -1,18 +1,28 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::rc::Rc;
 use gfx;
 use image;
 use find_folder::Search;
 use gfx::handle::{Buffer, DepthStencilView, RenderTargetView, Sampler, ShaderResourceView};
 use gfx::format::Formatted;
 use gfx::traits::FactoryExt;
-use cgmath::{Matrix4, Vector3};
+use cgmath::prelude::*;
+use cgmath::{self, Deg, Matrix3, Matrix4, Point3, Vector3};
 use camera::Camera;
+use font;
 
 pub type ColorFormat = gfx::format::Srgba8;
 pub type ShaderType = <ColorFormat as Formatted>::View;
 pub type DepthFormat = gfx::format::DepthStencil;
+pub type ShadowFormat = gfx::format::Depth32F;
 
 pub const BG: [f32; 4] = [0.0, 0.0, 0.0, 1.0];
 
+/// Instances uploaded per draw call; larger batches are split.
+pub const MAX_INSTANCES: usize = 16384;
+
 gfx_defines! {
     vertex Vertex {
         pos: [f32; 3] = "aPos",
@@ -20,12 +30,40 @@ gfx_defines! {
         uv: [f32; 2] = "aTexCoord",
     }
 
+    // matrices are passed by column, vertex attributes cannot be mat4
+    vertex Instance {
+        model0: [f32; 4] = "iModel0",
+        model1: [f32; 4] = "iModel1",
+        model2: [f32; 4] = "iModel2",
+        model3: [f32; 4] = "iModel3",
+        normal0: [f32; 3] = "iNormal0",
+        normal1: [f32; 3] = "iNormal1",
+        normal2: [f32; 3] = "iNormal2",
+    }
+
+    vertex LampInstance {
+        model0: [f32; 4] = "iModel0",
+        model1: [f32; 4] = "iModel1",
+        model2: [f32; 4] = "iModel2",
+        model3: [f32; 4] = "iModel3",
+        color: [f32; 3] = "iColor",
+    }
+
+    vertex TextVertex {
+        pos: [f32; 2] = "aPos",
+        uv: [f32; 2] = "aTexCoord",
+        color: [f32; 3] = "aColor",
+    }
+
     constant Transform {
-        model: [[f32; 4]; 4] = "model",
         view: [[f32; 4]; 4] = "view",
         projection: [[f32; 4]; 4] = "projection",
     }
 
+    constant ShadowCaster {
+        light_space: [[f32; 4]; 4] = "lightSpace",
+    }
+
     constant DirLight {
         ambient: [f32; 4] = "ambient", // align with 4 * 32
         diffuse: [f32; 4] = "diffuse",
@@ -44,30 +82,67 @@ gfx_defines! {
         pad: f32 = "pad",
     }
 
+    constant SpotLight {
+        ambient: [f32; 4] = "ambient", // align with 4 * 32
+        diffuse: [f32; 4] = "diffuse",
+        specular: [f32; 4] = "specular",
+        pos: [f32; 4] = "pos",
+        dir: [f32; 4] = "dir",
+        cut_off: f32 = "cut_off", // cosine of the inner cone angle
+        outer_cut_off: f32 = "outer_cut_off",
+        a0: f32 = "a0",
+        a1: f32 = "a1",
+        a2: f32 = "a2",
+        pad0: f32 = "pad0",
+        pad1: f32 = "pad1",
+        pad2: f32 = "pad2",
+    }
+
     constant LightArgs {
         num_dir: i32 = "num_dir",
         num_point: i32 = "num_point",
+        num_spot: i32 = "num_spot",
     }
 
     pipeline pipe {
         vbuf: gfx::VertexBuffer<Vertex> = (),
+        instances: gfx::InstanceBuffer<Instance> = (),
         transform: gfx::ConstantBuffer<Transform> = "Transform",
         dir_lights: gfx::ConstantBuffer<DirLight> = "u_dirLights",
         point_lights: gfx::ConstantBuffer<PointLight> = "u_pointLights",
+        spot_lights: gfx::ConstantBuffer<SpotLight> = "u_spotLights",
         light_args: gfx::ConstantBuffer<LightArgs> = "u_lightArgs",
         // TextureSampler cannot reside in constants? 'Copy trait not implemented'
         shininess: gfx::Global<f32> = "material_shininess",
         diffuse: gfx::TextureSampler<ShaderType> = "material_diffuse",
         specular: gfx::TextureSampler<ShaderType> = "material_specular",
         view_pos: gfx::Global<[f32; 3]> = "viewPos",
+        shadow_casters: gfx::ConstantBuffer<ShadowCaster> = "u_shadowCasters",
+        num_shadows: gfx::Global<i32> = "numShadows",
+        shadow_bias: gfx::Global<f32> = "shadowBias",
+        shadow_map: gfx::TextureSampler<f32> = "shadowMap",
         out: gfx::RenderTarget<ColorFormat> = "FragColor",
         out_depth: gfx::DepthTarget<DepthFormat> = gfx::preset::depth::LESS_EQUAL_WRITE,
     }
 
+    pipeline shadow_pipe {
+        vbuf: gfx::VertexBuffer<Vertex> = (),
+        instances: gfx::InstanceBuffer<Instance> = (),
+        caster: gfx::ConstantBuffer<ShadowCaster> = "u_shadowCaster",
+        out_depth: gfx::DepthTarget<ShadowFormat> = gfx::preset::depth::LESS_EQUAL_WRITE,
+    }
+
+    pipeline text_pipe {
+        vbuf: gfx::VertexBuffer<TextVertex> = (),
+        font: gfx::TextureSampler<[f32; 4]> = "font",
+        out: gfx::BlendTarget<ColorFormat> =
+            ("FragColor", gfx::state::ColorMask::all(), gfx::preset::blend::ALPHA),
+    }
+
     pipeline lamp_pipe {
         vbuf: gfx::VertexBuffer<Vertex> = (),
+        instances: gfx::InstanceBuffer<LampInstance> = (),
         transform: gfx::ConstantBuffer<Transform> = "Transform",
-        color: gfx::Global<[f32; 3]> = "light_color",
         out: gfx::RenderTarget<ColorFormat> = "FragColor",
         out_depth: gfx::DepthTarget<DepthFormat> = gfx::preset::depth::LESS_EQUAL_WRITE,
     }
@@ -79,6 +154,36 @@ impl Vertex {
     }
 }
 
+impl Instance {
+    pub fn new(model: Matrix4<f32>) -> Instance {
+        let normal = Matrix3::from_cols(model.x.truncate(), model.y.truncate(), model.z.truncate())
+            .invert()
+            .unwrap_or_else(Matrix3::identity)
+            .transpose();
+        Instance {
+            model0: model.x.into(),
+            model1: model.y.into(),
+            model2: model.z.into(),
+            model3: model.w.into(),
+            normal0: normal.x.into(),
+            normal1: normal.y.into(),
+            normal2: normal.z.into(),
+        }
+    }
+}
+
+impl LampInstance {
+    pub fn new(model: Matrix4<f32>, color: Vector3<f32>) -> LampInstance {
+        LampInstance {
+            model0: model.x.into(),
+            model1: model.y.into(),
+            model2: model.z.into(),
+            model3: model.w.into(),
+            color: color.into(),
+        }
+    }
+}
+
 impl DirLight {
     pub fn new(
         ambient: Vector3<f32>,
@@ -113,28 +218,311 @@ impl PointLight {
             pad: 0.0,
         }
     }
+
+    pub fn attenuation(mut self, a0: f32, a1: f32, a2: f32) -> PointLight {
+        self.a0 = a0;
+        self.a1 = a1;
+        self.a2 = a2;
+        self
+    }
 }
 
-pub fn load_texture<F, R>(factory: &mut F, path: &str) -> ShaderResourceView<R, ShaderType>
+impl SpotLight {
+    pub fn new(
+        ambient: Vector3<f32>,
+        diffuse: Vector3<f32>,
+        specular: Vector3<f32>,
+        pos: Vector3<f32>,
+        dir: Vector3<f32>,
+        cut_off: Deg<f32>,
+        outer_cut_off: Deg<f32>,
+    ) -> SpotLight {
+        SpotLight {
+            ambient: ambient.extend(1.0).into(),
+            diffuse: diffuse.extend(1.0).into(),
+            specular: specular.extend(1.0).into(),
+            pos: pos.extend(1.0).into(),
+            dir: dir.extend(0.0).into(),
+            cut_off: cut_off.cos(),
+            outer_cut_off: outer_cut_off.cos(),
+            a0: 1.0,
+            a1: 0.09,
+            a2: 0.032,
+            pad0: 0.0,
+            pad1: 0.0,
+            pad2: 0.0,
+        }
+    }
+
+    /// Places the light at the camera, pointing where the camera looks.
+    pub fn follow(&mut self, camera: &Camera) {
+        let pos = camera.pos();
+        self.pos = [pos.x, pos.y, pos.z, 1.0];
+        self.dir = camera.front().extend(0.0).into();
+    }
+}
+
+/// Why a texture file cannot be used, with the path it was given as.
+#[derive(Debug)]
+pub enum TextureError {
+    NotFound(String),
+    Image(String, image::ImageError),
+    TooLarge(String, u32, u32),
+    Upload(String, gfx::CombinedError),
+}
+
+impl fmt::Display for TextureError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            TextureError::NotFound(ref path) => write!(f, "texture \"{}\" not found", path),
+            TextureError::Image(ref path, ref e) => {
+                write!(f, "cannot load texture \"{}\": {}", path, e)
+            }
+            TextureError::TooLarge(ref path, width, height) => write!(
+                f,
+                "texture \"{}\" is {} x {}, larger than {} texels a side",
+                path,
+                width,
+                height,
+                gfx::texture::Size::MAX
+            ),
+            TextureError::Upload(ref path, ref e) => {
+                write!(f, "cannot upload texture \"{}\": {}", path, e)
+            }
+        }
+    }
+}
+
+impl Error for TextureError {}
+
+pub fn load_texture<F, R>(
+    factory: &mut F,
+    path: &str,
+) -> Result<ShaderResourceView<R, ShaderType>, TextureError>
 where
     F: gfx::Factory<R>,
     R: gfx::Resources,
 {
-    let path = Search::ParentsThenKids(4, 4).for_folder(path).unwrap();
-    let img = image::open(path).unwrap().to_rgba();
+    let found = Search::ParentsThenKids(4, 4)
+        .for_folder(path)
+        .map_err(|_| TextureError::NotFound(path.to_string()))?;
+    let img = image::open(found)
+        .map_err(|e| TextureError::Image(path.to_string(), e))?
+        .to_rgba();
     let (width, height) = img.dimensions();
-    let kind = gfx::texture::Kind::D2(width as u16, height as u16, gfx::texture::AaMode::Single);
+    let size = |n: u32| n as gfx::texture::Size;
+    if u32::from(size(width)) != width || u32::from(size(height)) != height {
+        return Err(TextureError::TooLarge(path.to_string(), width, height));
+    }
+    let kind = gfx::texture::Kind::D2(size(width), size(height), gfx::texture::AaMode::Single);
     let (_, view) = factory
         .create_texture_immutable_u8::<ColorFormat>(kind, &[&img])
+        .map_err(|e| TextureError::Upload(path.to_string(), e))?;
+    Ok(view)
+}
+
+/// Creates a 1x1 texture of a single color, for materials without a map.
+pub fn color_texture<F, R>(factory: &mut F, color: [f32; 3]) -> ShaderResourceView<R, ShaderType>
+where
+    F: gfx::Factory<R>,
+    R: gfx::Resources,
+{
+    let to_u8 = |c: f32| (c.clamp(0.0, 1.0) * 255.0).round() as u8;
+    let texel = [to_u8(color[0]), to_u8(color[1]), to_u8(color[2]), 255];
+    let kind = gfx::texture::Kind::D2(1, 1, gfx::texture::AaMode::Single);
+    let (_, view) = factory
+        .create_texture_immutable_u8::<ColorFormat>(kind, &[&texel])
         .unwrap();
     view
 }
 
+fn create_instance_buffer<F, R, T>(factory: &mut F) -> Buffer<R, T>
+where
+    F: gfx::Factory<R>,
+    R: gfx::Resources,
+{
+    factory
+        .create_buffer(
+            MAX_INSTANCES,
+            gfx::buffer::Role::Vertex,
+            gfx::memory::Usage::Dynamic,
+            gfx::Bind::empty(),
+        )
+        .expect("Cannot create instance buffer")
+}
+
+/// Copies `mesh.slice` to draw `count` instances.
+fn instanced_slice<R: gfx::Resources>(mesh: &Mesh<R>, count: usize) -> gfx::Slice<R> {
+    let mut slice = mesh.slice.clone();
+    slice.instances = Some((count as gfx::InstanceCount, 0));
+    slice
+}
+
+/// Array sizes of the light uniform blocks in `fragment.glsl`.
+pub const MAX_DIR_LIGHTS: usize = 16;
+pub const MAX_POINT_LIGHTS: usize = 64;
+pub const MAX_SPOT_LIGHTS: usize = 4;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum LightKind {
+    Directional,
+    Point,
+    Spot,
+}
+
+impl LightKind {
+    pub fn capacity(&self) -> usize {
+        match *self {
+            LightKind::Directional => MAX_DIR_LIGHTS,
+            LightKind::Point => MAX_POINT_LIGHTS,
+            LightKind::Spot => MAX_SPOT_LIGHTS,
+        }
+    }
+
+    pub fn all() -> [LightKind; 3] {
+        [LightKind::Directional, LightKind::Point, LightKind::Spot]
+    }
+
+    /// Position of the class in `LightKind::all()`.
+    pub fn index(&self) -> usize {
+        *self as usize
+    }
+}
+
+impl fmt::Display for LightKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match *self {
+            LightKind::Directional => "directional",
+            LightKind::Point => "point",
+            LightKind::Spot => "spot",
+        };
+        f.write_str(name)
+    }
+}
+
+/// Adding a light beyond what the shader can hold.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct LightCapacityError {
+    pub kind: LightKind,
+}
+
+impl fmt::Display for LightCapacityError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "too many {} lights, the shader supports at most {}",
+            self.kind,
+            self.kind.capacity()
+        )
+    }
+}
+
+impl Error for LightCapacityError {}
+
+/// Every light caster of a scene. Each class is bounded by the array size
+/// in the shader and can be switched off as a whole.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Lights {
+    dir: Vec<DirLight>,
+    point: Vec<PointLight>,
+    spot: Vec<SpotLight>,
+    enabled: [bool; 3],
+}
+
+impl Lights {
+    pub fn new() -> Lights {
+        Lights {
+            dir: Vec::new(),
+            point: Vec::new(),
+            spot: Vec::new(),
+            enabled: [true; 3],
+        }
+    }
+
+    fn check(&self, kind: LightKind, len: usize) -> Result<(), LightCapacityError> {
+        if len < kind.capacity() {
+            Ok(())
+        } else {
+            Err(LightCapacityError { kind })
+        }
+    }
+
+    pub fn add_dir(&mut self, light: DirLight) -> Result<(), LightCapacityError> {
+        self.check(LightKind::Directional, self.dir.len())?;
+        self.dir.push(light);
+        Ok(())
+    }
+
+    pub fn add_point(&mut self, light: PointLight) -> Result<(), LightCapacityError> {
+        self.check(LightKind::Point, self.point.len())?;
+        self.point.push(light);
+        Ok(())
+    }
+
+    pub fn add_spot(&mut self, light: SpotLight) -> Result<(), LightCapacityError> {
+        self.check(LightKind::Spot, self.spot.len())?;
+        self.spot.push(light);
+        Ok(())
+    }
+
+    pub fn dir(&self) -> &[DirLight] {
+        &self.dir
+    }
+
+    pub fn point(&self) -> &[PointLight] {
+        &self.point
+    }
+
+    pub fn spot(&self) -> &[SpotLight] {
+        &self.spot
+    }
+
+    /// Removes every light, keeping which classes are enabled.
+    pub fn clear(&mut self) {
+        self.dir.clear();
+        self.point.clear();
+        self.spot.clear();
+    }
+
+    pub fn set_enabled(&mut self, kind: LightKind, enabled: bool) {
+        self.enabled[kind.index()] = enabled;
+    }
+
+    pub fn is_enabled(&self, kind: LightKind) -> bool {
+        self.enabled[kind.index()]
+    }
+
+    /// The counts the shader iterates over; zero for disabled classes.
+    pub fn args(&self) -> LightArgs {
+        let count = |kind: LightKind, len: usize| if self.is_enabled(kind) {
+            len as i32
+        } else {
+            0
+        };
+        LightArgs {
+            num_dir: count(LightKind::Directional, self.dir.len()),
+            num_point: count(LightKind::Point, self.point.len()),
+            num_spot: count(LightKind::Spot, self.spot.len()),
+        }
+    }
+}
+
+impl Default for Lights {
+    fn default() -> Lights {
+        Lights::new()
+    }
+}
+
 pub struct ObjectBrush<R: gfx::Resources> {
     transform: Buffer<R, Transform>,
+    instances: Buffer<R, Instance>,
     dir_lights: Buffer<R, DirLight>,
     point_lights: Buffer<R, PointLight>,
+    spot_lights: Buffer<R, SpotLight>,
     light_args: Buffer<R, LightArgs>,
+    // what the light buffers currently hold, `None` before the first frame
+    uploaded: Option<Lights>,
+    view_pos: [f32; 3],
     pso: gfx::pso::PipelineState<R, pipe::Meta>,
     sampler: Sampler<R>,
 }
@@ -145,8 +533,10 @@ impl<R: gfx::Resources> ObjectBrush<R> {
         F: gfx::Factory<R>,
     {
         let transform = factory.create_constant_buffer(1);
-        let dir_lights = factory.create_constant_buffer(16);
-        let point_lights = factory.create_constant_buffer(64);
+        let instances = create_instance_buffer(factory);
+        let dir_lights = factory.create_constant_buffer(MAX_DIR_LIGHTS);
+        let point_lights = factory.create_constant_buffer(MAX_POINT_LIGHTS);
+        let spot_lights = factory.create_constant_buffer(MAX_SPOT_LIGHTS);
         let light_args = factory.create_constant_buffer(1);
         let pso = factory
             .create_pipeline_simple(
@@ -158,23 +548,24 @@ impl<R: gfx::Resources> ObjectBrush<R> {
         let sampler = factory.create_sampler_linear();
         ObjectBrush {
             transform,
+            instances,
             dir_lights,
             point_lights,
+            spot_lights,
             light_args,
+            uploaded: None,
+            view_pos: [0.0; 3],
             pso,
             sampler,
         }
     }
 
-    pub fn draw<C>(
-        &self,
-        object: &Object<R>,
-        dir_lights: &Vec<DirLight>,
-        point_lights: &Vec<PointLight>,
-        light_args: &LightArgs,
+    /// Uploads the per-frame uniforms: camera matrices always, each light
+    /// buffer only if it differs from what the previous frame uploaded.
+    pub fn begin_frame<C>(
+        &mut self,
         camera: &Camera,
-        render_target: &RenderTargetView<R, ColorFormat>,
-        depth: &DepthStencilView<R, DepthFormat>,
+        lights: &Lights,
         encoder: &mut gfx::Encoder<R, C>,
     ) where
         C: gfx::CommandBuffer<R>,
@@ -182,39 +573,239 @@ impl<R: gfx::Resources> ObjectBrush<R> {
         encoder.update_constant_buffer(
             &self.transform,
             &Transform {
-                model: object.model_mat.into(),
                 view: camera.view_matrix().into(),
                 projection: camera.projection_matrix().into(),
             },
         );
-        encoder
-            .update_buffer(&self.dir_lights, &dir_lights[..], 0)
-            .unwrap();
-        encoder
-            .update_buffer(&self.point_lights, &point_lights[..], 0)
-            .unwrap();
-        encoder.update_constant_buffer(&self.light_args, &light_args);
-        encoder.draw(
-            &object.slice,
-            &self.pso,
-            &pipe::Data {
-                vbuf: object.vertex_buffer.clone(),
-                transform: self.transform.clone(),
-                dir_lights: self.dir_lights.clone(),
-                point_lights: self.point_lights.clone(),
-                light_args: self.light_args.clone(),
-                shininess: object.material.shininess,
-                diffuse: (object.material.diffuse.clone(), self.sampler.clone()),
-                specular: (object.material.specular.clone(), self.sampler.clone()),
-                view_pos: camera.pos().into(),
-                out: render_target.clone(),
-                out_depth: depth.clone(),
-            },
-        );
+        self.view_pos = camera.pos().into();
+
+        if self.uploaded.as_ref() == Some(lights) {
+            return;
+        }
+        let (dir, point, spot, args) = match self.uploaded {
+            Some(ref old) => (
+                old.dir() != lights.dir(),
+                old.point() != lights.point(),
+                old.spot() != lights.spot(),
+                old.args() != lights.args(),
+            ),
+            None => (true, true, true, true),
+        };
+        if dir {
+            encoder
+                .update_buffer(&self.dir_lights, lights.dir(), 0)
+                .unwrap();
+        }
+        if point {
+            encoder
+                .update_buffer(&self.point_lights, lights.point(), 0)
+                .unwrap();
+        }
+        if spot {
+            encoder
+                .update_buffer(&self.spot_lights, lights.spot(), 0)
+                .unwrap();
+        }
+        if args {
+            encoder.update_constant_buffer(&self.light_args, &lights.args());
+        }
+        self.uploaded = Some(lights.clone());
+    }
+
+    /// Draws every instance of the batch; only the instance data and the
+    /// material change between calls within a frame.
+    pub fn draw<C>(
+        &self,
+        batch: &Batch<R>,
+        shadows: &ShadowBrush<R>,
+        render_target: &RenderTargetView<R, ColorFormat>,
+        depth: &DepthStencilView<R, DepthFormat>,
+        encoder: &mut gfx::Encoder<R, C>,
+    ) where
+        C: gfx::CommandBuffer<R>,
+    {
+        let data = pipe::Data {
+            vbuf: batch.mesh.vertex_buffer.clone(),
+            instances: self.instances.clone(),
+            transform: self.transform.clone(),
+            dir_lights: self.dir_lights.clone(),
+            point_lights: self.point_lights.clone(),
+            spot_lights: self.spot_lights.clone(),
+            light_args: self.light_args.clone(),
+            shininess: batch.material.shininess,
+            diffuse: (batch.material.diffuse.clone(), self.sampler.clone()),
+            specular: (batch.material.specular.clone(), self.sampler.clone()),
+            view_pos: self.view_pos,
+            shadow_casters: shadows.casters.clone(),
+            num_shadows: shadows.count as i32,
+            shadow_bias: shadows.bias,
+            shadow_map: (shadows.map.clone(), shadows.sampler.clone()),
+            out: render_target.clone(),
+            out_depth: depth.clone(),
+        };
+        for chunk in batch.instances.chunks(MAX_INSTANCES) {
+            encoder.update_buffer(&self.instances, chunk, 0).unwrap();
+            encoder.draw(&instanced_slice(&batch.mesh, chunk.len()), &self.pso, &data);
+        }
     }
 }
 
-#[derive(Clone)]
+/// Renders the depth of every object as seen from each directional light,
+/// one layer of a texture array per light.
+pub struct ShadowBrush<R: gfx::Resources> {
+    caster: Buffer<R, ShadowCaster>,
+    casters: Buffer<R, ShadowCaster>,
+    instances: Buffer<R, Instance>,
+    layers: Vec<DepthStencilView<R, ShadowFormat>>,
+    map: ShaderResourceView<R, f32>,
+    sampler: Sampler<R>,
+    pso: gfx::pso::PipelineState<R, shadow_pipe::Meta>,
+    /// Number of layers drawn this frame.
+    count: usize,
+    bias: f32,
+}
+
+impl<R: gfx::Resources> ShadowBrush<R> {
+    /// Shadow maps are `size` texels square. `bias` is the depth offset
+    /// against shadow acne on surfaces facing the light, ten times that on
+    /// surfaces at a grazing angle.
+    pub fn new<F>(factory: &mut F, size: u16, bias: f32) -> ShadowBrush<R>
+    where
+        F: gfx::Factory<R>,
+    {
+        use gfx::texture::{AaMode, DepthStencilFlags, FilterMethod, Kind, SamplerInfo, WrapMode};
+        let kind = Kind::D2Array(size, size, MAX_DIR_LIGHTS as gfx::texture::Layer, AaMode::Single);
+        let texture = factory
+            .create_texture::<<ShadowFormat as Formatted>::Surface>(
+                kind,
+                1,
+                gfx::DEPTH_STENCIL | gfx::SHADER_RESOURCE,
+                gfx::memory::Usage::Data,
+                Some(gfx::format::ChannelType::Float),
+            )
+            .expect("Cannot create shadow map");
+        let layers = (0..MAX_DIR_LIGHTS)
+            .map(|layer| {
+                factory
+                    .view_texture_as_depth_stencil::<ShadowFormat>(
+                        &texture,
+                        0,
+                        Some(layer as gfx::texture::Layer),
+                        DepthStencilFlags::empty(),
+                    )
+                    .expect("Cannot view shadow map layer")
+            })
+            .collect();
+        let map = factory
+            .view_texture_as_shader_resource::<ShadowFormat>(
+                &texture,
+                (0, 0),
+                gfx::format::Swizzle::new(),
+            )
+            .expect("Cannot view shadow map");
+        // outside the map is as far from the light as it gets
+        let mut info = SamplerInfo::new(FilterMethod::Scale, WrapMode::Border);
+        info.border = [1.0, 1.0, 1.0, 1.0].into();
+        let sampler = factory.create_sampler(info);
+        let pso = factory
+            .create_pipeline_simple(
+                include_bytes!("shader/shadow_vertex.glsl"),
+                include_bytes!("shader/shadow_fragment.glsl"),
+                shadow_pipe::new(),
+            )
+            .expect("Cannot create PSO for shadows");
+        ShadowBrush {
+            caster: factory.create_constant_buffer(1),
+            casters: factory.create_constant_buffer(MAX_DIR_LIGHTS),
+            instances: create_instance_buffer(factory),
+            layers,
+            map,
+            sampler,
+            pso,
+            count: 0,
+            bias,
+        }
+    }
+
+    /// Draws the shadow maps of the enabled directional lights, each
+    /// covering a sphere around all batches.
+    pub fn draw<C>(
+        &mut self,
+        lights: &Lights,
+        batches: &[Batch<R>],
+        encoder: &mut gfx::Encoder<R, C>,
+    ) where
+        C: gfx::CommandBuffer<R>,
+    {
+        let count = lights.args().num_dir as usize;
+        self.count = count;
+        if count == 0 {
+            return;
+        }
+        let (center, radius) = bounds(batches);
+        let mut casters = [ShadowCaster { light_space: Matrix4::identity().into() }; MAX_DIR_LIGHTS];
+        for (i, light) in lights.dir()[..count].iter().enumerate() {
+            let dir = Vector3::new(light.dir[0], light.dir[1], light.dir[2]).normalize();
+            casters[i].light_space = light_space(dir, center, radius).into();
+            encoder.update_constant_buffer(&self.caster, &casters[i]);
+            encoder.clear_depth(&self.layers[i], 1.0);
+            for batch in batches {
+                let data = shadow_pipe::Data {
+                    vbuf: batch.mesh.vertex_buffer.clone(),
+                    instances: self.instances.clone(),
+                    caster: self.caster.clone(),
+                    out_depth: self.layers[i].clone(),
+                };
+                for chunk in batch.instances.chunks(MAX_INSTANCES) {
+                    encoder.update_buffer(&self.instances, chunk, 0).unwrap();
+                    encoder.draw(&instanced_slice(&batch.mesh, chunk.len()), &self.pso, &data);
+                }
+            }
+        }
+        encoder.update_buffer(&self.casters, &casters, 0).unwrap();
+    }
+}
+
+/// A sphere containing every instance of `batches`.
+fn bounds<R: gfx::Resources>(batches: &[Batch<R>]) -> (Point3<f32>, f32) {
+    let spheres: Vec<(Point3<f32>, f32)> = batches
+        .iter()
+        .flat_map(|batch| {
+            batch.instances.iter().map(move |instance| {
+                let m = [instance.model0, instance.model1, instance.model2];
+                let scale = m.iter()
+                    .map(|c| Vector3::new(c[0], c[1], c[2]).magnitude())
+                    .fold(0.0, f32::max);
+                let t = instance.model3;
+                (Point3::new(t[0], t[1], t[2]), scale * batch.mesh.radius)
+            })
+        })
+        .collect();
+    if spheres.is_empty() {
+        return (Point3::origin(), 1.0);
+    }
+    let center = Point3::centroid(&spheres.iter().map(|s| s.0).collect::<Vec<_>>());
+    let radius = spheres
+        .iter()
+        .map(|&(p, r)| p.distance(center) + r)
+        .fold(0.0, f32::max);
+    (center, radius.max(0.1))
+}
+
+/// The view and orthographic projection of a light shining along `dir`
+/// onto the sphere at `center`.
+fn light_space(dir: Vector3<f32>, center: Point3<f32>, radius: f32) -> Matrix4<f32> {
+    let up = if dir.x.abs() < 1e-3 && dir.z.abs() < 1e-3 {
+        Vector3::unit_z()
+    } else {
+        Vector3::unit_y()
+    };
+    let eye = center + dir * (-2.0 * radius);
+    let view = Matrix4::look_at(eye, center, up);
+    cgmath::ortho(-radius, radius, -radius, radius, radius, 3.0 * radius) * view
+}
+
+#[derive(Clone, PartialEq)]
 pub struct Material<R: gfx::Resources> {
     pub diffuse: ShaderResourceView<R, ShaderType>,
     pub specular: ShaderResourceView<R, ShaderType>,
@@ -227,50 +818,123 @@ impl<R: gfx::Resources> Material<R> {
         diffuse_texture_path: &str,
         specular_texture_path: &str,
         shininess: f32,
-    ) -> Material<R>
+    ) -> Result<Material<R>, TextureError>
     where
         F: gfx::Factory<R>,
     {
-        let diffuse = load_texture(factory, diffuse_texture_path);
-        let specular = load_texture(factory, specular_texture_path);
-        Material {
+        let diffuse = load_texture(factory, diffuse_texture_path)?;
+        let specular = load_texture(factory, specular_texture_path)?;
+        Ok(Material {
             diffuse,
             specular,
             shininess,
-        }
+        })
     }
 }
 
-pub struct Object<R: gfx::Resources> {
+/// GPU geometry with duplicate vertices welded into an index buffer. Wrap
+/// it in an `Rc` to share one upload among many objects and lamps.
+pub struct Mesh<R: gfx::Resources> {
     pub vertex_buffer: Buffer<R, Vertex>,
     pub slice: gfx::Slice<R>,
-    pub model_mat: Matrix4<f32>,
-    pub material: Material<R>,
+    /// Distance of the farthest vertex from the mesh origin.
+    pub radius: f32,
 }
 
-impl<R: gfx::Resources> Object<R> {
-    pub fn new<F>(
-        factory: &mut F,
-        vertices: Vec<Vertex>,
-        model_mat: Matrix4<f32>,
-        material: Material<R>,
-    ) -> Object<R>
+impl<R: gfx::Resources> Mesh<R> {
+    pub fn new<F>(factory: &mut F, vertices: &[Vertex]) -> Mesh<R>
     where
         F: gfx::Factory<R>,
     {
+        let (vertices, indices) = weld(vertices);
         let (vertex_buffer, slice) =
-            factory.create_vertex_buffer_with_slice(vertices.as_slice(), ());
-        Object {
+            factory.create_vertex_buffer_with_slice(vertices.as_slice(), indices.as_slice());
+        let radius = vertices
+            .iter()
+            .map(|v| Vector3::from(v.pos).magnitude())
+            .fold(0.0, f32::max);
+        Mesh {
             vertex_buffer,
             slice,
+            radius,
+        }
+    }
+}
+
+/// Merges bitwise identical vertices of a triangle list, returning the
+/// unique vertices and the indices that rebuild the list.
+pub fn weld(vertices: &[Vertex]) -> (Vec<Vertex>, Vec<u32>) {
+    let mut unique = Vec::new();
+    let mut indices = Vec::with_capacity(vertices.len());
+    let mut seen = HashMap::new();
+    for v in vertices {
+        let mut key = [0u32; 8];
+        for (k, f) in key.iter_mut().zip(v.pos.iter().chain(&v.normal).chain(&v.uv)) {
+            *k = f.to_bits();
+        }
+        let index = *seen.entry(key).or_insert_with(|| {
+            unique.push(*v);
+            unique.len() as u32 - 1
+        });
+        indices.push(index);
+    }
+    (unique, indices)
+}
+
+pub struct Object<R: gfx::Resources> {
+    pub mesh: Rc<Mesh<R>>,
+    pub model_mat: Matrix4<f32>,
+    pub material: Material<R>,
+}
+
+impl<R: gfx::Resources> Object<R> {
+    pub fn new(mesh: Rc<Mesh<R>>, model_mat: Matrix4<f32>, material: Material<R>) -> Object<R> {
+        Object {
+            mesh,
             model_mat,
             material,
         }
     }
 }
 
+/// Objects sharing a mesh and a material, drawn with one instanced call.
+pub struct Batch<R: gfx::Resources> {
+    pub mesh: Rc<Mesh<R>>,
+    pub material: Material<R>,
+    pub instances: Vec<Instance>,
+}
+
+impl<R: gfx::Resources> Batch<R> {
+    /// Groups objects by mesh and material, keeping the order in which each
+    /// combination first appears.
+    pub fn group<'a, I>(objects: I) -> Vec<Batch<R>>
+    where
+        I: IntoIterator<Item = (&'a Rc<Mesh<R>>, &'a Material<R>, Matrix4<f32>)>,
+        R: 'a,
+    {
+        let mut batches: Vec<Batch<R>> = Vec::new();
+        for (mesh, material, model_mat) in objects {
+            let instance = Instance::new(model_mat);
+            match batches.iter_mut().find(|b| {
+                Rc::ptr_eq(&b.mesh, mesh) && b.material == *material
+            }) {
+                Some(batch) => batch.instances.push(instance),
+                None => {
+                    batches.push(Batch {
+                        mesh: mesh.clone(),
+                        material: material.clone(),
+                        instances: vec![instance],
+                    })
+                }
+            }
+        }
+        batches
+    }
+}
+
 pub struct LampBrush<R: gfx::Resources> {
     transform: Buffer<R, Transform>,
+    instances: Buffer<R, LampInstance>,
     pso: gfx::pso::PipelineState<R, lamp_pipe::Meta>,
 }
 
@@ -280,6 +944,7 @@ impl<R: gfx::Resources> LampBrush<R> {
         F: gfx::Factory<R>,
     {
         let transform = factory.create_constant_buffer(1);
+        let instances = create_instance_buffer(factory);
         let pso = factory
             .create_pipeline_simple(
                 include_bytes!("shader/light_vertex.glsl"),
@@ -287,65 +952,188 @@ impl<R: gfx::Resources> LampBrush<R> {
                 lamp_pipe::new(),
             )
             .expect("Cannot create PSO for lamp");
-        LampBrush { transform, pso }
+        LampBrush {
+            transform,
+            instances,
+            pso,
+        }
     }
 
-    pub fn draw<C>(
-        &self,
-        lamp: &Lamp<R>,
-        camera: &Camera,
-        render_target: &RenderTargetView<R, ColorFormat>,
-        depth: &DepthStencilView<R, DepthFormat>,
-        encoder: &mut gfx::Encoder<R, C>,
-    ) where
+    pub fn begin_frame<C>(&self, camera: &Camera, encoder: &mut gfx::Encoder<R, C>)
+    where
         C: gfx::CommandBuffer<R>,
     {
         encoder.update_constant_buffer(
             &self.transform,
             &Transform {
-                model: lamp.model_mat.into(),
                 view: camera.view_matrix().into(),
                 projection: camera.projection_matrix().into(),
             },
         );
-        encoder.draw(
-            &lamp.slice,
-            &self.pso,
-            &lamp_pipe::Data {
-                vbuf: lamp.vertex_buffer.clone(),
+    }
+
+    /// Draws the lamps with one instanced call per distinct mesh.
+    pub fn draw<'a, C, I>(
+        &self,
+        lamps: I,
+        render_target: &RenderTargetView<R, ColorFormat>,
+        depth: &DepthStencilView<R, DepthFormat>,
+        encoder: &mut gfx::Encoder<R, C>,
+    ) where
+        C: gfx::CommandBuffer<R>,
+        I: IntoIterator<Item = (&'a Rc<Mesh<R>>, LampInstance)>,
+        R: 'a,
+    {
+        let mut groups: Vec<(&Rc<Mesh<R>>, Vec<LampInstance>)> = Vec::new();
+        for (mesh, instance) in lamps {
+            match groups.iter_mut().find(|g| Rc::ptr_eq(g.0, mesh)) {
+                Some(group) => group.1.push(instance),
+                None => groups.push((mesh, vec![instance])),
+            }
+        }
+        for (mesh, instances) in groups {
+            let data = lamp_pipe::Data {
+                vbuf: mesh.vertex_buffer.clone(),
+                instances: self.instances.clone(),
                 transform: self.transform.clone(),
-                color: lamp.color.into(),
                 out: render_target.clone(),
                 out_depth: depth.clone(),
-            },
-        );
+            };
+            for chunk in instances.chunks(MAX_INSTANCES) {
+                encoder.update_buffer(&self.instances, chunk, 0).unwrap();
+                encoder.draw(&instanced_slice(mesh, chunk.len()), &self.pso, &data);
+            }
+        }
     }
 }
 
-pub struct Lamp<R: gfx::Resources> {
-    pub vertex_buffer: Buffer<R, Vertex>,
-    pub slice: gfx::Slice<R>,
-    pub model_mat: Matrix4<f32>,
-    pub color: Vector3<f32>,
+/// Characters drawn per frame by `TextBrush`; the rest are dropped.
+pub const MAX_TEXT_CHARS: usize = 4096;
+
+/// Draws lines of text on top of the frame with the bitmap font in `font`.
+pub struct TextBrush<R: gfx::Resources> {
+    vertex_buffer: Buffer<R, TextVertex>,
+    vertices: Vec<TextVertex>,
+    font: ShaderResourceView<R, [f32; 4]>,
+    sampler: Sampler<R>,
+    pso: gfx::pso::PipelineState<R, text_pipe::Meta>,
+    /// Size of a font pixel in screen pixels.
+    scale: f32,
 }
 
-impl<R: gfx::Resources> Lamp<R> {
-    pub fn new<F>(
-        factory: &mut F,
-        vertices: Vec<Vertex>,
-        model_mat: Matrix4<f32>,
-        color: Vector3<f32>,
-    ) -> Lamp<R>
+impl<R: gfx::Resources> TextBrush<R> {
+    pub fn new<F>(factory: &mut F, scale: f32) -> TextBrush<R>
     where
         F: gfx::Factory<R>,
     {
-        let (vertex_buffer, slice) =
-            factory.create_vertex_buffer_with_slice(vertices.as_slice(), ());
-        Lamp {
+        let vertex_buffer = factory
+            .create_buffer(
+                MAX_TEXT_CHARS * 6,
+                gfx::buffer::Role::Vertex,
+                gfx::memory::Usage::Dynamic,
+                gfx::Bind::empty(),
+            )
+            .expect("Cannot create text vertex buffer");
+        let kind = gfx::texture::Kind::D2(
+            (font::glyph_count() * font::GLYPH_WIDTH) as u16,
+            font::GLYPH_HEIGHT as u16,
+            gfx::texture::AaMode::Single,
+        );
+        let (_, font) = factory
+            .create_texture_immutable_u8::<gfx::format::Rgba8>(kind, &[&font::atlas()])
+            .unwrap();
+        let sampler = factory.create_sampler(gfx::texture::SamplerInfo::new(
+            gfx::texture::FilterMethod::Scale,
+            gfx::texture::WrapMode::Clamp,
+        ));
+        let pso = factory
+            .create_pipeline_simple(
+                include_bytes!("shader/text_vertex.glsl"),
+                include_bytes!("shader/text_fragment.glsl"),
+                text_pipe::new(),
+            )
+            .expect("Cannot create PSO for text");
+        TextBrush {
             vertex_buffer,
-            slice,
-            model_mat,
-            color,
+            vertices: Vec::new(),
+            font,
+            sampler,
+            pso,
+            scale,
         }
     }
+
+    /// Height of a line of text in screen pixels.
+    pub fn line_height(&self) -> f32 {
+        (font::GLYPH_HEIGHT + 2) as f32 * self.scale
+    }
+
+    /// Queues `text` with its top left corner at (`x`, `y`) pixels from the
+    /// top left of a `width` x `height` target. Lines are split at `\n`.
+    pub fn queue(&mut self, text: &str, x: f32, y: f32, color: [f32; 3], width: f32, height: f32) {
+        let advance = (font::GLYPH_WIDTH + 1) as f32 * self.scale;
+        let (w, h) = (
+            font::GLYPH_WIDTH as f32 * self.scale,
+            font::GLYPH_HEIGHT as f32 * self.scale,
+        );
+        let atlas_width = font::glyph_count() as f32;
+        let to_ndc = |px: f32, py: f32| [px / width * 2.0 - 1.0, 1.0 - py / height * 2.0];
+
+        for (row, line) in text.lines().enumerate() {
+            let top = y + row as f32 * self.line_height();
+            for (col, c) in line.chars().enumerate() {
+                if c == ' ' {
+                    continue;
+                }
+                if self.vertices.len() + 6 > MAX_TEXT_CHARS * 6 {
+                    return;
+                }
+                let left = x + col as f32 * advance;
+                let u0 = font::glyph_index(c) as f32 / atlas_width;
+                let u1 = u0 + 1.0 / atlas_width;
+                let corner = |px: f32, py: f32, u: f32, v: f32| {
+                    TextVertex {
+                        pos: to_ndc(px, py),
+                        uv: [u, v],
+                        color,
+                    }
+                };
+                let tl = corner(left, top, u0, 0.0);
+                let tr = corner(left + w, top, u1, 0.0);
+                let bl = corner(left, top + h, u0, 1.0);
+                let br = corner(left + w, top + h, u1, 1.0);
+                self.vertices.extend_from_slice(&[tl, bl, br, tl, br, tr]);
+            }
+        }
+    }
+
+    /// Draws and clears the queued text.
+    pub fn draw<C>(
+        &mut self,
+        render_target: &RenderTargetView<R, ColorFormat>,
+        encoder: &mut gfx::Encoder<R, C>,
+    ) where
+        C: gfx::CommandBuffer<R>,
+    {
+        if self.vertices.is_empty() {
+            return;
+        }
+        encoder
+            .update_buffer(&self.vertex_buffer, &self.vertices, 0)
+            .unwrap();
+        let slice = gfx::Slice {
+            start: 0,
+            end: self.vertices.len() as u32,
+            base_vertex: 0,
+            instances: None,
+            buffer: gfx::IndexBuffer::Auto,
+        };
+        let data = text_pipe::Data {
+            vbuf: self.vertex_buffer.clone(),
+            font: (self.font.clone(), self.sampler.clone()),
+            out: render_target.clone(),
+        };
+        encoder.draw(&slice, &self.pso, &data);
+        self.vertices.clear();
+    }
 }
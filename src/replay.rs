@@ -0,0 +1,133 @@
+//! Recorded input sessions. A recording is a text file with a `frame <dt>`
+//! line for every frame, giving its duration in seconds, followed by the
+//! `InputEvent`s received in that frame, one per line. Replaying the frames
+//! with the same durations reproduces the session.
+
+use std::error::Error;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufWriter, Read, Write};
+use std::path::Path;
+use input::InputEvent;
+
+#[derive(Debug)]
+pub enum ReplayError {
+    Io(io::Error),
+    Parse(usize, String),
+}
+
+impl fmt::Display for ReplayError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ReplayError::Io(ref e) => write!(f, "cannot read input recording: {}", e),
+            ReplayError::Parse(line, ref msg) => write!(f, "line {}: {}", line, msg),
+        }
+    }
+}
+
+impl Error for ReplayError {}
+
+impl From<io::Error> for ReplayError {
+    fn from(e: io::Error) -> ReplayError {
+        ReplayError::Io(e)
+    }
+}
+
+/// The input of one frame and how long the frame took.
+#[derive(Debug, Clone, PartialEq)]
+pub struct InputFrame {
+    pub dt: f32,
+    pub events: Vec<InputEvent>,
+}
+
+pub fn load<P: AsRef<Path>>(path: P) -> Result<Vec<InputFrame>, ReplayError> {
+    let mut text = String::new();
+    File::open(path)?.read_to_string(&mut text)?;
+    parse(&text)
+}
+
+pub fn parse(text: &str) -> Result<Vec<InputFrame>, ReplayError> {
+    let mut frames: Vec<InputFrame> = Vec::new();
+    for (i, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let error = |msg: String| ReplayError::Parse(i + 1, msg);
+        if let Some(dt) = line.strip_prefix("frame ") {
+            let dt: f32 = dt
+                .trim()
+                .parse()
+                .map_err(|_| error(format!("invalid frame time \"{}\"", dt)))?;
+            if !dt.is_finite() || dt < 0.0 {
+                return Err(error("frame time must be 0 or positive".to_string()));
+            }
+            frames.push(InputFrame { dt, events: Vec::new() });
+        } else {
+            let event = line.parse().map_err(error)?;
+            match frames.last_mut() {
+                Some(frame) => frame.events.push(event),
+                None => return Err(error("input event before the first frame".to_string())),
+            }
+        }
+    }
+    Ok(frames)
+}
+
+/// Writes frames to a recording as they happen, so that it survives a crash.
+pub struct InputRecorder {
+    out: BufWriter<File>,
+}
+
+impl InputRecorder {
+    pub fn create<P: AsRef<Path>>(path: P) -> io::Result<InputRecorder> {
+        let out = BufWriter::new(File::create(path)?);
+        Ok(InputRecorder { out })
+    }
+
+    pub fn record(&mut self, frame: &InputFrame) -> io::Result<()> {
+        writeln!(self.out, "frame {}", frame.dt)?;
+        for event in &frame.events {
+            writeln!(self.out, "{}", event)?;
+        }
+        self.out.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glutin::{MouseButton, VirtualKeyCode};
+    use input::InputEvent;
+    use super::*;
+
+    #[test]
+    fn events_survive_a_round_trip() {
+        let frame = InputFrame {
+            dt: 1.0 / 60.0,
+            events: vec![
+                InputEvent::Key(VirtualKeyCode::W, true),
+                InputEvent::MouseMove(-3, 400),
+                InputEvent::MouseButton(MouseButton::Other(4), false),
+                InputEvent::Scroll(-0.5),
+                InputEvent::Resize(800, 600),
+                InputEvent::Focus,
+                InputEvent::MouseEnter,
+                InputEvent::Close,
+            ],
+        };
+        let mut text = format!("frame {}\n", frame.dt);
+        for event in &frame.events {
+            text += &format!("{}\n", event);
+        }
+        assert_eq!(parse(&text).unwrap(), vec![frame]);
+    }
+
+    #[test]
+    fn rejects_events_outside_frames() {
+        match parse("key W down\n") {
+            Err(ReplayError::Parse(1, _)) => {}
+            other => panic!("unexpected {:?}", other),
+        }
+        assert!(parse("frame 0.1\nkey Nope down\n").is_err());
+    }
+}
@@ -0,0 +1,776 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+use std::rc::Rc;
+use cgmath::prelude::*;
+use cgmath::{Deg, Matrix4, Point3, Quaternion, Rad, Vector3};
+use find_folder::Search;
+use gfx;
+use gfx_device_gl;
+use toml;
+use camera::{Camera, CameraBuilder, Projection};
+use component::{Animation, Drawable, Keyframe, Light, Motion, Transform};
+use model;
+use obj::{self, Normals, ObjError};
+use render;
+use world::World;
+
+/// Name of the built-in cube mesh from `model::vertices()`.
+pub const CUBE_MESH: &str = "cube";
+
+/// Size of the cubes marking point lights.
+const LAMP_SCALE: f32 = 0.2;
+
+#[derive(Debug)]
+pub enum SceneError {
+    Io(io::Error),
+    Parse(toml::de::Error),
+    Invalid(String),
+    Mesh(String, ObjError),
+    Lights(render::LightCapacityError),
+}
+
+impl fmt::Display for SceneError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            SceneError::Io(ref e) => write!(f, "cannot read scene file: {}", e),
+            SceneError::Parse(ref e) => write!(f, "malformed scene file: {}", e),
+            SceneError::Invalid(ref msg) => write!(f, "invalid scene: {}", msg),
+            SceneError::Mesh(ref path, ref e) => write!(f, "mesh \"{}\": {}", path, e),
+            SceneError::Lights(ref e) => write!(f, "invalid scene: {}", e),
+        }
+    }
+}
+
+impl Error for SceneError {}
+
+impl From<io::Error> for SceneError {
+    fn from(e: io::Error) -> SceneError {
+        SceneError::Io(e)
+    }
+}
+
+impl From<render::LightCapacityError> for SceneError {
+    fn from(e: render::LightCapacityError) -> SceneError {
+        SceneError::Lights(e)
+    }
+}
+
+impl From<toml::de::Error> for SceneError {
+    fn from(e: toml::de::Error) -> SceneError {
+        SceneError::Parse(e)
+    }
+}
+
+struct ObjPart<R: gfx::Resources> {
+    name: String,
+    mesh: Rc<render::Mesh<R>>,
+    material: Option<render::Material<R>>,
+}
+
+fn load_obj<F, R>(factory: &mut F, mesh: &str, normals: Normals) -> Result<Vec<ObjPart<R>>, SceneError>
+where
+    F: gfx::Factory<R>,
+    R: gfx::Resources,
+{
+    let path = Search::ParentsThenKids(4, 4)
+        .for_folder(mesh)
+        .map_err(|_| SceneError::Invalid(format!("mesh \"{}\" not found", mesh)))?;
+    let meshes = obj::load(&path, normals).map_err(
+        |e| SceneError::Mesh(mesh.to_string(), e),
+    )?;
+    let mut parts = Vec::new();
+    for m in meshes {
+        let material = match m.material {
+            Some(mtl) => Some(
+                mtl.material(factory)
+                    .map_err(|e| SceneError::Mesh(mesh.to_string(), e))?,
+            ),
+            None => None,
+        };
+        parts.push(ObjPart {
+            mesh: Rc::new(render::Mesh::new(factory, &m.vertices)),
+            material,
+            name: m.name,
+        });
+    }
+    Ok(parts)
+}
+
+fn default_camera_pos() -> [f32; 3] {
+    [0.0, 0.0, 3.0]
+}
+
+fn default_yaw() -> f32 {
+    -90.0
+}
+
+fn default_fov() -> f32 {
+    45.0
+}
+
+fn default_fov_range() -> [f32; 2] {
+    [1.0, 45.0]
+}
+
+fn default_near() -> f32 {
+    0.1
+}
+
+fn default_far() -> f32 {
+    100.0
+}
+
+fn default_height() -> f32 {
+    10.0
+}
+
+fn default_height_range() -> [f32; 2] {
+    [0.1, 1000.0]
+}
+
+fn default_scale() -> f32 {
+    1.0
+}
+
+fn default_axis() -> [f32; 3] {
+    [0.0, 1.0, 0.0]
+}
+
+fn default_attenuation() -> [f32; 3] {
+    [1.0, 0.09, 0.032]
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CameraDesc {
+    #[serde(default = "default_camera_pos")]
+    pub position: [f32; 3],
+    #[serde(default = "default_yaw")]
+    pub yaw: f32,
+    #[serde(default)]
+    pub pitch: f32,
+    #[serde(default = "default_fov")]
+    pub fov: f32,
+    #[serde(default = "default_fov_range")]
+    pub fov_range: [f32; 2],
+    #[serde(default = "default_near")]
+    pub near: f32,
+    #[serde(default = "default_far")]
+    pub far: f32,
+    #[serde(default)]
+    pub projection: ProjectionDesc,
+    /// View height of the orthographic projection.
+    #[serde(default = "default_height")]
+    pub height: f32,
+    #[serde(default = "default_height_range")]
+    pub height_range: [f32; 2],
+}
+
+#[derive(Debug, Copy, Clone, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ProjectionDesc {
+    #[default]
+    Perspective,
+    InfinitePerspective,
+    Orthographic,
+}
+
+impl Default for CameraDesc {
+    fn default() -> CameraDesc {
+        CameraDesc {
+            position: default_camera_pos(),
+            yaw: default_yaw(),
+            pitch: 0.0,
+            fov: default_fov(),
+            fov_range: default_fov_range(),
+            near: default_near(),
+            far: default_far(),
+            projection: ProjectionDesc::default(),
+            height: default_height(),
+            height_range: default_height_range(),
+        }
+    }
+}
+
+impl CameraDesc {
+    pub fn builder(&self) -> CameraBuilder {
+        let projection = match self.projection {
+            ProjectionDesc::Perspective => Projection::Perspective,
+            ProjectionDesc::InfinitePerspective => Projection::InfinitePerspective,
+            ProjectionDesc::Orthographic => Projection::Orthographic { height: self.height },
+        };
+        CameraBuilder::new(Point3::from(self.position), Vector3::unit_y())
+            .yaw(self.yaw)
+            .pitch(self.pitch)
+            .fov(self.fov)
+            .fov_range(self.fov_range[0], self.fov_range[1])
+            .height_range(self.height_range[0], self.height_range[1])
+            .near(self.near)
+            .far(self.far)
+            .projection(projection)
+    }
+
+    fn validate(&self) -> Result<(), SceneError> {
+        let invalid = |msg: String| Err(SceneError::Invalid(format!("camera {}", msg)));
+        let [min, max] = self.fov_range;
+        if !(min > 0.0 && min <= max && max < 180.0) {
+            return invalid(format!("fov_range {}..{} is not within 0..180 degrees", min, max));
+        }
+        if !(self.fov >= min && self.fov <= max) {
+            return invalid(format!("fov {} is outside {}..{} degrees", self.fov, min, max));
+        }
+        if !(self.pitch >= -89.0 && self.pitch <= 89.0) {
+            return invalid(format!("pitch {} is outside -89..89 degrees", self.pitch));
+        }
+        if !(self.near > 0.0 && self.near.is_finite()) {
+            return invalid(format!("near plane {} is not positive", self.near));
+        }
+        let has_far = self.projection != ProjectionDesc::InfinitePerspective;
+        if has_far && !(self.far > self.near && self.far.is_finite()) {
+            return invalid(format!("far plane {} is not beyond the near plane", self.far));
+        }
+        let [min, max] = self.height_range;
+        if !(min > 0.0 && min <= max && max.is_finite()) {
+            return invalid(format!("height_range {}..{} is not positive", min, max));
+        }
+        if !(self.height >= min && self.height <= max) {
+            return invalid(format!("height {} is outside {}..{}", self.height, min, max));
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MaterialDesc {
+    pub diffuse: String,
+    pub specular: String,
+    pub shininess: f32,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ObjectDesc {
+    /// Either `"cube"` or the path of a Wavefront OBJ file.
+    pub mesh: String,
+    /// Overrides the materials of an OBJ file; required for `"cube"`.
+    #[serde(default)]
+    pub material: Option<String>,
+    /// Normals generated for an OBJ file that has none.
+    #[serde(default)]
+    pub normals: Normals,
+    pub position: [f32; 3],
+    #[serde(default = "default_axis")]
+    pub rotation_axis: [f32; 3],
+    /// Rotation around `rotation_axis` in degrees.
+    #[serde(default)]
+    pub rotation_angle: f32,
+    #[serde(default = "default_scale")]
+    pub scale: f32,
+    #[serde(default)]
+    pub animation: Option<AnimationDesc>,
+}
+
+impl ObjectDesc {
+    pub fn model_matrix(&self) -> Matrix4<f32> {
+        Matrix4::from_translation(self.position.into()) * self.base_matrix()
+    }
+
+    /// Rotation and scale, without the translation.
+    pub fn base_matrix(&self) -> Matrix4<f32> {
+        Matrix4::from_axis_angle(
+            Vector3::from(self.rotation_axis).normalize(),
+            Deg(self.rotation_angle),
+        ) * Matrix4::from_scale(self.scale)
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct KeyframeDesc {
+    /// Seconds from the start of the track.
+    pub time: f32,
+    pub position: [f32; 3],
+    /// Rotation on top of the entity's own, in degrees around the axis.
+    #[serde(default = "default_axis")]
+    pub rotation_axis: [f32; 3],
+    #[serde(default)]
+    pub rotation_angle: f32,
+    /// Scale on top of the entity's own.
+    #[serde(default = "default_scale")]
+    pub scale: f32,
+    /// Diffuse and specular color of a point light.
+    #[serde(default)]
+    pub color: Option<[f32; 3]>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase", deny_unknown_fields)]
+pub enum AnimationDesc {
+    Keyframes { keys: Vec<KeyframeDesc> },
+    Orbit {
+        center: [f32; 3],
+        radius: f32,
+        #[serde(default = "default_axis")]
+        axis: [f32; 3],
+        /// Degrees per second.
+        speed: f32,
+        /// Starting angle in degrees.
+        #[serde(default)]
+        phase: f32,
+    },
+}
+
+impl AnimationDesc {
+    /// `base` is the rotation and scale of the animated entity.
+    pub fn animation(&self, base: Matrix4<f32>) -> Animation {
+        let motion = match *self {
+            AnimationDesc::Keyframes { ref keys } => {
+                Motion::Keyframes(
+                    keys.iter()
+                        .map(|key| {
+                            Keyframe {
+                                time: key.time,
+                                translation: key.position.into(),
+                                rotation: Quaternion::from_axis_angle(
+                                    Vector3::from(key.rotation_axis).normalize(),
+                                    Deg(key.rotation_angle),
+                                ),
+                                scale: key.scale,
+                                color: key.color.map(Vector3::from),
+                            }
+                        })
+                        .collect(),
+                )
+            }
+            AnimationDesc::Orbit {
+                center,
+                radius,
+                axis,
+                speed,
+                phase,
+            } => {
+                Motion::Orbit {
+                    center: center.into(),
+                    axis: axis.into(),
+                    radius,
+                    speed,
+                    phase,
+                }
+            }
+        };
+        Animation::new(motion, base)
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        match *self {
+            AnimationDesc::Keyframes { ref keys } => {
+                if keys.is_empty() {
+                    return Err("animation has no keys".to_string());
+                }
+                if keys.windows(2).any(|w| w[1].time <= w[0].time) {
+                    return Err("animation key times must increase".to_string());
+                }
+                if keys.iter().any(|k| Vector3::from(k.rotation_axis).is_zero()) {
+                    return Err("animation key rotation axis is zero".to_string());
+                }
+            }
+            AnimationDesc::Orbit { radius, axis, .. } => {
+                if radius < 0.0 {
+                    return Err("orbit radius is negative".to_string());
+                }
+                if Vector3::from(axis).is_zero() {
+                    return Err("orbit axis is zero".to_string());
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DirLightDesc {
+    pub ambient: [f32; 3],
+    pub diffuse: [f32; 3],
+    pub specular: [f32; 3],
+    pub direction: [f32; 3],
+}
+
+impl DirLightDesc {
+    pub fn light(&self) -> render::DirLight {
+        render::DirLight::new(
+            self.ambient.into(),
+            self.diffuse.into(),
+            self.specular.into(),
+            self.direction.into(),
+        )
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PointLightDesc {
+    pub ambient: [f32; 3],
+    pub diffuse: [f32; 3],
+    pub specular: [f32; 3],
+    pub position: [f32; 3],
+    /// Constant, linear and quadratic attenuation terms.
+    #[serde(default = "default_attenuation")]
+    pub attenuation: [f32; 3],
+    /// Moves the light and its lamp.
+    #[serde(default)]
+    pub animation: Option<AnimationDesc>,
+}
+
+impl PointLightDesc {
+    pub fn light(&self) -> render::PointLight {
+        let [a0, a1, a2] = self.attenuation;
+        render::PointLight::new(
+            self.ambient.into(),
+            self.diffuse.into(),
+            self.specular.into(),
+            self.position.into(),
+        ).attenuation(a0, a1, a2)
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Scene {
+    #[serde(default)]
+    pub camera: CameraDesc,
+    #[serde(default)]
+    pub materials: HashMap<String, MaterialDesc>,
+    #[serde(default, rename = "object")]
+    pub objects: Vec<ObjectDesc>,
+    #[serde(default, rename = "dir_light")]
+    pub dir_lights: Vec<DirLightDesc>,
+    #[serde(default, rename = "point_light")]
+    pub point_lights: Vec<PointLightDesc>,
+}
+
+impl Scene {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Scene, SceneError> {
+        let mut text = String::new();
+        File::open(path)?.read_to_string(&mut text)?;
+        Scene::parse(&text)
+    }
+
+    pub fn parse(text: &str) -> Result<Scene, SceneError> {
+        let scene: Scene = toml::from_str(text)?;
+        scene.validate()?;
+        Ok(scene)
+    }
+
+    /// The scene that used to be hardcoded: ten rotated containers lit by
+    /// one directional and four point lights.
+    pub fn builtin() -> Scene {
+        let mut materials = HashMap::new();
+        materials.insert(
+            "container".to_string(),
+            MaterialDesc {
+                diffuse: "textures/container2.png".to_string(),
+                specular: "textures/container2_specular.png".to_string(),
+                shininess: 32.0,
+            },
+        );
+        let objects = model::cube_positions()
+            .into_iter()
+            .enumerate()
+            .map(|(i, pos)| {
+                ObjectDesc {
+                    mesh: CUBE_MESH.to_string(),
+                    material: Some("container".to_string()),
+                    normals: Normals::default(),
+                    position: pos.into(),
+                    rotation_axis: [1.0, 0.3, 0.5],
+                    rotation_angle: Deg::from(Rad(20.0 * i as f32)).0,
+                    scale: 1.0,
+                    animation: None,
+                }
+            })
+            .collect();
+        let dir_lights = model::light_directions()
+            .into_iter()
+            .map(|dir| {
+                DirLightDesc {
+                    ambient: [0.05; 3],
+                    diffuse: [0.3; 3],
+                    specular: [1.0; 3],
+                    direction: dir.into(),
+                }
+            })
+            .collect();
+        let point_lights = model::light_positions()
+            .into_iter()
+            .map(|pos| {
+                PointLightDesc {
+                    ambient: [0.05; 3],
+                    diffuse: [0.3; 3],
+                    specular: [1.0; 3],
+                    position: pos.into(),
+                    attenuation: default_attenuation(),
+                    animation: None,
+                }
+            })
+            .collect();
+        Scene {
+            camera: CameraDesc::default(),
+            materials,
+            objects,
+            dir_lights,
+            point_lights,
+        }
+    }
+
+    pub fn camera(&self, width: f32, height: f32) -> Camera {
+        self.camera.builder().aspect(width, height).build()
+    }
+
+    pub fn lights(&self) -> Result<render::Lights, SceneError> {
+        let mut lights = render::Lights::new();
+        for light in &self.dir_lights {
+            lights.add_dir(light.light())?;
+        }
+        for light in &self.point_lights {
+            lights.add_point(light.light())?;
+        }
+        Ok(lights)
+    }
+
+    /// Uploads every object, loading OBJ files and their MTL materials.
+    /// Each mesh is uploaded once and shared by all objects using it;
+    /// `cube` is used for the built-in cube mesh. An OBJ file gives one
+    /// object per model it contains, all paired with the same description.
+    pub fn objects<F, R>(
+        &self,
+        factory: &mut F,
+        cube: &Rc<render::Mesh<R>>,
+    ) -> Result<Vec<(&ObjectDesc, render::Object<R>)>, SceneError>
+    where
+        F: gfx::Factory<R>,
+        R: gfx::Resources,
+    {
+        let mut materials = HashMap::new();
+        for (name, mat) in &self.materials {
+            let material =
+                render::Material::new(factory, &mat.diffuse, &mat.specular, mat.shininess)
+                    .map_err(|e| SceneError::Invalid(format!("material \"{}\": {}", name, e)))?;
+            materials.insert(name.clone(), material);
+        }
+        let mut loaded = HashMap::new();
+
+        let mut objects = Vec::new();
+        for desc in &self.objects {
+            let model_mat = desc.model_matrix();
+            let material = desc.material.as_ref().map(|name| materials[name].clone());
+            if desc.mesh == CUBE_MESH {
+                objects.push((desc, render::Object::new(cube.clone(), model_mat, material.unwrap())));
+                continue;
+            }
+
+            let key = (desc.mesh.clone(), desc.normals);
+            if !loaded.contains_key(&key) {
+                let parts = load_obj(factory, &desc.mesh, desc.normals)?;
+                loaded.insert(key.clone(), parts);
+            }
+            for part in &loaded[&key] {
+                let material = match (material.clone(), part.material.clone()) {
+                    (Some(material), _) | (None, Some(material)) => material,
+                    (None, None) => {
+                        return Err(SceneError::Invalid(format!(
+                            "mesh \"{}\": part \"{}\" has no material",
+                            desc.mesh,
+                            part.name
+                        )))
+                    }
+                };
+                objects.push((desc, render::Object::new(part.mesh.clone(), model_mat, material)));
+            }
+        }
+        Ok(objects)
+    }
+
+    /// Creates an entity for every object and light. Point lights also get
+    /// a small lamp cube made of `cube`, in the color of their specular term.
+    /// Fails if the world then holds more lights than the shader supports.
+    pub fn spawn<F>(
+        &self,
+        world: &mut World,
+        factory: &mut F,
+        cube: &Rc<render::Mesh<gfx_device_gl::Resources>>,
+    ) -> Result<(), SceneError>
+    where
+        F: gfx::Factory<gfx_device_gl::Resources>,
+    {
+        for (desc, object) in self.objects(factory, cube)? {
+            let entity = world
+                .create_entity()
+                .with(Transform::new(object.model_mat))
+                .with(Drawable::Object {
+                    mesh: object.mesh,
+                    material: object.material,
+                })
+                .build();
+            if let Some(ref animation) = desc.animation {
+                world.animations.insert(entity, animation.animation(desc.base_matrix()));
+            }
+        }
+        for light in &self.dir_lights {
+            world
+                .create_entity()
+                .with(Light::Directional(light.light()))
+                .build();
+        }
+        for light in &self.point_lights {
+            let base = Matrix4::from_scale(LAMP_SCALE);
+            let model = Matrix4::from_translation(light.position.into()) * base;
+            let entity = world
+                .create_entity()
+                .with(Transform::new(model))
+                .with(Light::Point(light.light()))
+                .with(Drawable::Lamp {
+                    mesh: cube.clone(),
+                    color: light.specular.into(),
+                })
+                .build();
+            if let Some(ref animation) = light.animation {
+                world.animations.insert(entity, animation.animation(base));
+            }
+        }
+        world.collect_lights(&mut render::Lights::new())?;
+        Ok(())
+    }
+
+    fn validate(&self) -> Result<(), SceneError> {
+        self.lights()?;
+        self.camera.validate()?;
+        for (i, obj) in self.objects.iter().enumerate() {
+            let is_obj = obj.mesh.to_lowercase().ends_with(".obj");
+            if obj.mesh != CUBE_MESH && !is_obj {
+                return Err(SceneError::Invalid(format!(
+                    "object #{}: unknown mesh \"{}\"",
+                    i,
+                    obj.mesh
+                )));
+            }
+            match obj.material {
+                Some(ref name) if !self.materials.contains_key(name) => {
+                    return Err(SceneError::Invalid(format!(
+                        "object #{}: undefined material \"{}\"",
+                        i,
+                        name
+                    )));
+                }
+                None if !is_obj => {
+                    return Err(SceneError::Invalid(
+                        format!("object #{}: a cube needs a material", i),
+                    ));
+                }
+                _ => {}
+            }
+            if Vector3::from(obj.rotation_axis).is_zero() {
+                return Err(SceneError::Invalid(
+                    format!("object #{}: rotation axis is zero", i),
+                ));
+            }
+            if let Some(ref animation) = obj.animation {
+                animation.validate().map_err(|msg| {
+                    SceneError::Invalid(format!("object #{}: {}", i, msg))
+                })?;
+            }
+        }
+        for (i, light) in self.dir_lights.iter().enumerate() {
+            if Vector3::from(light.direction).is_zero() {
+                return Err(SceneError::Invalid(
+                    format!("directional light #{}: direction is zero", i),
+                ));
+            }
+        }
+        for (i, light) in self.point_lights.iter().enumerate() {
+            if light.attenuation.iter().any(|a| *a < 0.0) ||
+                light.attenuation.iter().all(|a| *a == 0.0)
+            {
+                return Err(SceneError::Invalid(format!(
+                    "point light #{}: attenuation {:?} must be non-negative and not all zero",
+                    i,
+                    light.attenuation
+                )));
+            }
+            if let Some(ref animation) = light.animation {
+                animation.validate().map_err(|msg| {
+                    SceneError::Invalid(format!("point light #{}: {}", i, msg))
+                })?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tobj;
+    use super::*;
+
+    fn parse_error(text: &str) -> SceneError {
+        match Scene::parse(text) {
+            Ok(_) => panic!("{:?} parsed", text),
+            Err(e) => e,
+        }
+    }
+
+    #[test]
+    fn rejects_unreadable_files() {
+        match Scene::load("no/such/scene.toml") {
+            Err(SceneError::Io(_)) => {}
+            other => panic!("unexpected {:?}", other),
+        }
+        match parse_error("[camera\n") {
+            SceneError::Parse(_) => {}
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_descriptions() {
+        let scenes = [
+            "[[object]]\nmesh = \"cube\"\nmaterial = \"missing\"\nposition = [0.0, 0.0, 0.0]",
+            "[camera]\nfov = 60.0",
+            "[camera]\nnear = 10.0\nfar = 10.0",
+            "[camera]\npitch = 90.0",
+        ];
+        for text in scenes.iter() {
+            match parse_error(text) {
+                SceneError::Invalid(_) => {}
+                other => panic!("unexpected {:?} for {:?}", other, text),
+            }
+        }
+        assert!(Scene::parse("[camera]\npitch = 89.0\nprojection = \"infinite_perspective\"").is_ok());
+    }
+
+    #[test]
+    fn rejects_too_many_lights() {
+        let mut scene = Scene::builtin();
+        assert!(scene.validate().is_ok());
+        let light = scene.dir_lights[0].clone();
+        scene.dir_lights.resize(render::MAX_DIR_LIGHTS + 1, light);
+        match scene.validate() {
+            Err(SceneError::Lights(_)) => {}
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn mesh_errors_name_the_mesh() {
+        let e = SceneError::Mesh(
+            "teapot.obj".to_string(),
+            ObjError::Load(tobj::LoadError::OpenFileFailed),
+        );
+        assert_eq!(e.to_string(), "mesh \"teapot.obj\": cannot load OBJ file: open file failed");
+    }
+}
@@ -20,6 +20,16 @@ struct PointLight {
     float a0, a1, a2, pad;
 };
 
+struct SpotLight {
+    vec4 ambient;
+    vec4 diffuse;
+    vec4 specular;
+    vec4 pos;
+    vec4 dir;
+    float cut_off, outer_cut_off;
+    float a0, a1, a2, pad0, pad1, pad2;
+};
+
 uniform u_dirLights {
     DirLight dirLights[16];
 };
@@ -28,17 +38,54 @@ uniform u_pointLights {
     PointLight pointLights[64];
 };
 
+uniform u_spotLights {
+    SpotLight spotLights[4];
+};
+
 uniform u_lightArgs {
     int num_dir;
     int num_point;
+    int num_spot;
 };
 
+struct ShadowCaster {
+    mat4 lightSpace;
+};
+
+// one per shadow map layer, for each directional light
+uniform u_shadowCasters {
+    ShadowCaster shadowCasters[16];
+};
+
+uniform int numShadows;
+uniform float shadowBias;
+uniform sampler2DArray shadowMap;
+
 uniform float material_shininess;
 uniform sampler2D material_diffuse;
 uniform sampler2D material_specular;
 uniform vec3 viewPos;
 
-vec4 CalcDirLight(DirLight light, vec4 normal, vec4 viewDir)
+// fraction of the 3x3 shadow map texels around the fragment that are
+// closer to the light than the fragment
+float ShadowPCF(int layer, vec4 fragPosLightSpace, float bias)
+{
+    vec3 projCoords = fragPosLightSpace.xyz / fragPosLightSpace.w * 0.5 + 0.5;
+    // beyond the far plane of the light
+    if (projCoords.z > 1.0)
+        return 0.0;
+    vec2 texelSize = 1.0 / vec2(textureSize(shadowMap, 0).xy);
+    float shadow = 0.0;
+    for (int x = -1; x <= 1; x++)
+        for (int y = -1; y <= 1; y++) {
+            vec2 uv = projCoords.xy + vec2(x, y) * texelSize;
+            float closest = texture(shadowMap, vec3(uv, layer)).r;
+            shadow += projCoords.z - bias > closest ? 1.0 : 0.0;
+        }
+    return shadow / 9.0;
+}
+
+vec4 CalcDirLight(DirLight light, vec4 normal, vec4 viewDir, float shadow)
 {
     vec4 lightDir = normalize(-light.dir);
     // diffuse shading
@@ -50,7 +97,7 @@ vec4 CalcDirLight(DirLight light, vec4 normal, vec4 viewDir)
     vec4 ambient  = light.ambient  * texture(material_diffuse, TexCoords);
     vec4 diffuse  = light.diffuse  * diff * texture(material_diffuse, TexCoords);
     vec4 specular = light.specular * spec * texture(material_specular, TexCoords);
-    return (ambient + diffuse + specular);
+    return (ambient + (1.0 - shadow) * (diffuse + specular));
 }
 
 vec4 CalcPointLight(PointLight light, vec4 normal, vec4 fragPos, vec4 viewDir)
@@ -74,6 +121,31 @@ vec4 CalcPointLight(PointLight light, vec4 normal, vec4 fragPos, vec4 viewDir)
     return (ambient + diffuse + specular);
 }
 
+vec4 CalcSpotLight(SpotLight light, vec4 normal, vec4 fragPos, vec4 viewDir)
+{
+    vec4 lightDir = normalize(light.pos - fragPos);
+    // diffuse shading
+    float diff = max(dot(normal, lightDir), 0.0);
+    // specular shading
+    vec4 reflectDir = reflect(-lightDir, normal);
+    float spec = pow(max(dot(viewDir, reflectDir), 0.0), material_shininess);
+    // attenuation
+    float distance    = length(light.pos - fragPos);
+    float attenuation = 1.0 / (light.a0 + light.a1 * distance + light.a2 * (distance * distance));
+    // soft edge between the inner and outer cone
+    float theta     = dot(lightDir, normalize(-light.dir));
+    float epsilon   = light.cut_off - light.outer_cut_off;
+    float intensity = clamp((theta - light.outer_cut_off) / epsilon, 0.0, 1.0);
+    // combine results
+    vec4 ambient  = light.ambient  * texture(material_diffuse, TexCoords);
+    vec4 diffuse  = light.diffuse  * diff * texture(material_diffuse, TexCoords);
+    vec4 specular = light.specular * spec * texture(material_specular, TexCoords);
+    ambient  *= attenuation;
+    diffuse  *= attenuation * intensity;
+    specular *= attenuation * intensity;
+    return (ambient + diffuse + specular);
+}
+
 void main()
 {
     // properties
@@ -82,13 +154,22 @@ void main()
 
     vec4 result = vec4(0.0);
     // phase 1: Directional lighting
-    for(int i = 0; i < num_dir; i++)
-        result = CalcDirLight(dirLights[i], norm, viewDir);
+    for(int i = 0; i < num_dir; i++) {
+        float shadow = 0.0;
+        if (i < numShadows) {
+            // surfaces at a grazing angle to the light need more bias
+            float cosine = dot(norm, normalize(-dirLights[i].dir));
+            float bias = max(10.0 * shadowBias * (1.0 - cosine), shadowBias);
+            shadow = ShadowPCF(i, shadowCasters[i].lightSpace * vec4(FragPos, 1.0), bias);
+        }
+        result += CalcDirLight(dirLights[i], norm, viewDir, shadow);
+    }
     // phase 2: Point lights
     for(int i = 0; i < num_point; i++)
-        result += CalcPointLight(pointLights[i], norm, vec4(FragPos, 1.0), viewDir);    
-    // phase 3: Spot light
-    //result += CalcSpotLight(spotLight, norm, FragPos, viewDir);    
+        result += CalcPointLight(pointLights[i], norm, vec4(FragPos, 1.0), viewDir);
+    // phase 3: Spot lights
+    for(int i = 0; i < num_spot; i++)
+        result += CalcSpotLight(spotLights[i], norm, vec4(FragPos, 1.0), viewDir);
     
     FragColor = result;
 }
@@ -1,9 +1,9 @@
 #version 330 core
 out vec4 FragColor;
 
-uniform vec3 light_color;
+in vec3 LightColor;
 
 void main()
 {
-    FragColor = vec4(light_color, 1.0);
+    FragColor = vec4(LightColor, 1.0);
 }
@@ -1,19 +1,22 @@
 #version 330 core
 layout (location = 0) in vec3 aPos;
-layout (location = 1) in vec3 aNormal;
+// per instance
+in vec4 iModel0;
+in vec4 iModel1;
+in vec4 iModel2;
+in vec4 iModel3;
+in vec3 iColor;
 
-out vec3 Normal;
-out vec3 FragPos;
+out vec3 LightColor;
 
 uniform Transform {
-    mat4 model;
     mat4 view;
     mat4 projection;
 };
 
 void main()
 {
+    mat4 model = mat4(iModel0, iModel1, iModel2, iModel3);
     gl_Position = projection * view * model * vec4(aPos, 1.0);
-    FragPos = vec3(model * vec4(aPos, 1.0));
-    Normal = mat3(transpose(inverse(model))) * aNormal;
+    LightColor = iColor;
 }
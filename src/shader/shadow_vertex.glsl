@@ -0,0 +1,17 @@
+#version 330 core
+layout (location = 0) in vec3 aPos;
+// per instance
+in vec4 iModel0;
+in vec4 iModel1;
+in vec4 iModel2;
+in vec4 iModel3;
+
+uniform u_shadowCaster {
+    mat4 lightSpace;
+};
+
+void main()
+{
+    mat4 model = mat4(iModel0, iModel1, iModel2, iModel3);
+    gl_Position = lightSpace * model * vec4(aPos, 1.0);
+}
@@ -0,0 +1,12 @@
+#version 330 core
+out vec4 FragColor;
+
+in vec2 TexCoord;
+in vec3 TextColor;
+
+uniform sampler2D font;
+
+void main()
+{
+    FragColor = vec4(TextColor, texture(font, TexCoord).a);
+}
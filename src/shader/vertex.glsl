@@ -2,21 +2,30 @@
 layout (location = 0) in vec3 aPos;
 layout (location = 1) in vec3 aNormal;
 layout (location = 2) in vec2 aTexCoord;
+// per instance
+in vec4 iModel0;
+in vec4 iModel1;
+in vec4 iModel2;
+in vec4 iModel3;
+in vec3 iNormal0;
+in vec3 iNormal1;
+in vec3 iNormal2;
 
 out vec3 Normal;
 out vec3 FragPos;
 out vec2 TexCoords;
 
 uniform Transform {
-    mat4 model;
     mat4 view;
     mat4 projection;
 };
 
 void main()
 {
+    mat4 model = mat4(iModel0, iModel1, iModel2, iModel3);
+    mat3 normalMatrix = mat3(iNormal0, iNormal1, iNormal2);
     gl_Position = projection * view * model * vec4(aPos, 1.0);
     FragPos = vec3(model * vec4(aPos, 1.0));
-    Normal = mat3(transpose(inverse(model))) * aNormal;
+    Normal = normalMatrix * aNormal;
     TexCoords = aTexCoord;
 }
@@ -0,0 +1,46 @@
+use cgmath::Vector3;
+use component::{Drawable, Light, Transform};
+use context::Context;
+use system::System;
+use world::World;
+
+/// Advances every `Animation` and applies its pose. Lights move with their
+/// entity's `Transform`; keyframe colors are copied to the light's diffuse
+/// and specular terms and to its lamp. The light selected for editing is
+/// paused, so that its pose and color are left to `LightEditSystem`.
+pub struct AnimationSystem;
+
+impl System for AnimationSystem {
+    fn run(&mut self, _ctx: &mut Context, world: &mut World, dt: f32) {
+        let selected = world.selected_light;
+        for (entity, animation) in world.animations.iter_mut() {
+            if selected == Some(entity) {
+                continue;
+            }
+            animation.time += dt;
+            let pose = animation.sample();
+            world.transforms.insert(entity, Transform::new(pose.model));
+
+            let color: Vector3<f32> = match pose.color {
+                Some(color) => color,
+                None => continue,
+            };
+            match world.lights.get_mut(entity) {
+                Some(&mut Light::Point(ref mut light)) => {
+                    light.diffuse = color.extend(1.0).into();
+                    light.specular = color.extend(1.0).into();
+                }
+                Some(&mut Light::Spot(ref mut light)) => {
+                    light.diffuse = color.extend(1.0).into();
+                    light.specular = color.extend(1.0).into();
+                }
+                _ => {}
+            }
+            if let Some(&mut Drawable::Lamp { color: ref mut lamp, .. }) =
+                world.drawables.get_mut(entity)
+            {
+                *lamp = color;
+            }
+        }
+    }
+}
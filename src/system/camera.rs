@@ -1,74 +1,194 @@
-use std::collections::HashMap;
-use glutin::VirtualKeyCode;
-use cgmath::{Matrix4, Point3};
+use std::path::{Path, PathBuf};
+use glutin::MouseButton;
 use camera::{Camera, MovementDirection as MD};
+use camera_path::{CameraPath, CameraSample};
 use context::Context;
+use input::Action;
 use system::System;
+use world::World;
 
-lazy_static! {
-    static ref KEY_MAP: HashMap<VirtualKeyCode, MD> = {
-        let mut m = HashMap::new();
-        m.insert(VirtualKeyCode::W, MD::Up);
-        m.insert(VirtualKeyCode::S, MD::Down);
-        m.insert(VirtualKeyCode::A, MD::Left);
-        m.insert(VirtualKeyCode::D, MD::Right);
-        m
-    };
-}
+/// Degrees per second the camera rolls in 6-DoF mode.
+const ROLL_SPEED: f32 = 90.0;
+/// Fraction of the orbit distance one scroll line dollies.
+const DOLLY_SPEED: f32 = 0.1;
+/// Fraction of the orbit distance one pixel of dragging pans.
+const PAN_SPEED: f32 = 0.002;
+
+const MOVES: [(Action, MD); 6] = [
+    (Action::MoveForward, MD::Up),
+    (Action::MoveBackward, MD::Down),
+    (Action::StrafeLeft, MD::Left),
+    (Action::StrafeRight, MD::Right),
+    (Action::MoveUp, MD::Ascend),
+    (Action::MoveDown, MD::Descend),
+];
 
+/// Moves every camera in the world with the movement actions and the mouse.
+/// `ToggleFreeLook` switches them between yaw/pitch and 6-DoF mode, where
+/// they can also roll.
+///
+/// `ToggleOrbit` makes them circle a point instead: dragging with the left
+/// button rotates around it, scrolling dollies and dragging with the middle
+/// button or while `Pan` is held pans.
+///
+/// `RecordCamera` records the path of the first camera and saves it when
+/// pressed again; `PlayCamera` moves the cameras along the loaded or last
+/// recorded path instead of following the input.
 pub struct CameraSystem {
-    camera: Camera,
-    yaw: f32,
-    pitch: f32,
     sensitivity: f32,
+    record_file: PathBuf,
+    /// Recording time and samples so far.
+    recording: Option<(f32, CameraPath)>,
+    path: Option<CameraPath>,
+    /// Time into `path` while playing it.
+    playback: Option<f32>,
 }
 
 impl CameraSystem {
-    pub fn new(camera: Camera, sensitivity: f32) -> CameraSystem {
+    pub fn new(sensitivity: f32) -> CameraSystem {
         CameraSystem {
-            camera,
-            yaw: -90.0,
-            pitch: 0.0,
             sensitivity,
+            record_file: PathBuf::from("camera_path.toml"),
+            recording: None,
+            path: None,
+            playback: None,
+        }
+    }
+
+    /// Saves recorded paths to `file` instead of `camera_path.toml`.
+    pub fn record_to<P: AsRef<Path>>(mut self, file: P) -> CameraSystem {
+        self.record_file = file.as_ref().to_path_buf();
+        self
+    }
+
+    /// Starts playing `path` right away.
+    pub fn play(mut self, path: CameraPath) -> CameraSystem {
+        self.path = Some(path);
+        self.playback = Some(0.0);
+        self
+    }
+
+    fn toggle_recording(&mut self) {
+        match self.recording.take() {
+            Some((_, path)) => {
+                if path.is_empty() {
+                    return;
+                }
+                match path.save(&self.record_file) {
+                    Ok(()) => println!("> camera path saved to {}", self.record_file.display()),
+                    Err(e) => eprintln!("{}: {}", self.record_file.display(), e),
+                }
+                self.path = Some(path);
+            }
+            None => {
+                self.recording = Some((0.0, CameraPath::default()));
+                println!("> recording camera path");
+            }
         }
     }
 
-    pub fn projection_matrix(&self) -> Matrix4<f32> {
-        self.camera.projection_matrix()
+    fn toggle_playback(&mut self) {
+        if self.playback.take().is_some() {
+            println!("> camera path stopped");
+        } else if self.path.is_some() {
+            self.playback = Some(0.0);
+            println!("> playing camera path");
+        } else {
+            println!("> no camera path to play");
+        }
     }
 
-    pub fn view_matrix(&self) -> Matrix4<f32> {
-        self.camera.view_matrix()
+    /// Where the cameras are while playing a path, advancing it by `dt`.
+    fn playback_sample(&mut self, dt: f32) -> Option<CameraSample> {
+        let time = self.playback?;
+        let path = self.path.as_ref()?;
+        if time > path.duration() {
+            self.playback = None;
+            println!("> camera path finished");
+            return None;
+        }
+        self.playback = Some(time + dt);
+        Some(path.sample(time))
     }
 
-    #[allow(dead_code)]
-    pub fn position(&self) -> Point3<f32> {
-        self.camera.pos()
+    fn fly(&self, ctx: &Context, camera: &mut Camera, scroll: f32, dt: f32) {
+        let delta = ctx.input.mouse_state.delta();
+        let roll = match (ctx.input.is_active(Action::RollLeft), ctx.input.is_active(Action::RollRight)) {
+            (true, false) => -1.0,
+            (false, true) => 1.0,
+            _ => 0.0,
+        };
+        for &(action, dir) in &MOVES {
+            camera.move_towards(dir, ctx.input.is_active(action));
+        }
+        camera.sprint(ctx.input.is_active(Action::Sprint));
+        camera.turn(
+            delta.x * self.sensitivity,
+            -delta.y * self.sensitivity,
+            roll * ROLL_SPEED * dt,
+        );
+        camera.zoom(scroll);
+        camera.move_for(dt);
     }
 
-    pub fn camera(&self) -> &Camera {
-        &self.camera
+    fn orbit(&self, ctx: &Context, camera: &mut Camera, scroll: f32) {
+        let delta = ctx.input.mouse_state.delta();
+        let left = ctx.input.mouse_state.is_pressed(MouseButton::Left);
+        let middle = ctx.input.mouse_state.is_pressed(MouseButton::Middle);
+        if middle || (left && ctx.input.is_active(Action::Pan)) {
+            camera.pan(-delta.x * PAN_SPEED, delta.y * PAN_SPEED);
+        } else if left {
+            camera.turn(delta.x * self.sensitivity, -delta.y * self.sensitivity, 0.0);
+        }
+        camera.dolly(scroll * DOLLY_SPEED);
     }
 }
 
 impl System for CameraSystem {
-    fn run(&mut self, ctx: &mut Context, dt: f32) {
-        self.camera.update_aspect(
-            ctx.screen_width as f32,
-            ctx.screen_height as f32,
-        );
-        for (key, value) in KEY_MAP.iter() {
-            self.camera.move_towards(
-                *value,
-                ctx.key_state.is_pressed(*key),
-            );
+    fn run(&mut self, ctx: &mut Context, world: &mut World, dt: f32) {
+        let scroll = ctx.input.mouse_state.drain_scroll();
+        // pressed twice since the last tick is no change
+        let mut toggle_free = false;
+        while ctx.input.take_press(Action::ToggleFreeLook) {
+            toggle_free = !toggle_free;
+        }
+        let mut toggle_orbit = false;
+        while ctx.input.take_press(Action::ToggleOrbit) {
+            toggle_orbit = !toggle_orbit;
+        }
+        while ctx.input.take_press(Action::RecordCamera) {
+            self.toggle_recording();
+        }
+        while ctx.input.take_press(Action::PlayCamera) {
+            self.toggle_playback();
+        }
+        let sample = self.playback_sample(dt);
+        for (_, camera) in world.cameras.iter_mut() {
+            camera.update_aspect(ctx.input.screen_width as f32, ctx.input.screen_height as f32);
+            if toggle_free {
+                let free = !camera.is_free();
+                camera.set_free(free);
+                println!("> 6-DoF camera {}", if free { "on" } else { "off" });
+            }
+            if toggle_orbit {
+                let orbit = !camera.is_orbiting();
+                camera.set_orbit(orbit);
+                println!("> orbit camera {}", if orbit { "on" } else { "off" });
+            }
+            if let Some(sample) = sample {
+                sample.apply(camera);
+            } else if camera.is_orbiting() {
+                self.orbit(ctx, camera, scroll);
+            } else {
+                self.fly(ctx, camera, scroll, dt);
+            }
+        }
+        if let Some((ref mut time, ref mut path)) = self.recording {
+            if let Some((_, camera)) = world.cameras.iter().next() {
+                path.record(*time, camera);
+            }
+            *time += dt;
         }
-        let delta = ctx.mouse_state.delta();
-        self.yaw += delta.x * self.sensitivity;
-        self.pitch -= delta.y * self.sensitivity;
-        self.camera.look_around(self.pitch, self.yaw);
-        self.camera.zoom(ctx.mouse_state.drain_scroll());
-        self.camera.move_for(dt);
-        ctx.reset_mouse_pos();
+        ctx.input.reset_mouse_pos();
     }
 }
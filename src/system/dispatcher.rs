@@ -0,0 +1,198 @@
+use std::time::{Duration, Instant};
+use context::Context;
+use system::System;
+use world::World;
+
+/// When a system runs within a frame. Stages run in declaration order and
+/// systems within a stage in the order they were added.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Stage {
+    Input,
+    Update,
+    Render,
+}
+
+/// Asks the dispatcher to do something at the start of the next frame.
+#[derive(Debug, Clone, PartialEq)]
+pub enum DispatcherRequest {
+    Toggle(&'static str),
+    ReportTimings,
+}
+
+/// CPU time spent in one system.
+#[derive(Debug, Copy, Clone, Default)]
+pub struct Timing {
+    /// Time spent in the latest frame, over all of its update ticks.
+    pub last: Duration,
+    pub total: Duration,
+    /// Number of runs `total` is spread over.
+    pub runs: u32,
+}
+
+impl Timing {
+    pub fn average(&self) -> Duration {
+        if self.runs == 0 {
+            Duration::from_secs(0)
+        } else {
+            self.total / self.runs
+        }
+    }
+}
+
+struct Entry {
+    name: &'static str,
+    stage: Stage,
+    enabled: bool,
+    timing: Timing,
+    system: Box<dyn System>,
+}
+
+/// Longest frame time fed into the update accumulator, so that a stall
+/// does not cause a burst of catch-up ticks.
+const MAX_FRAME_TIME: f32 = 0.25;
+
+/// Runs the registered systems stage by stage and times each of them.
+///
+/// `Update` systems run at a fixed tick rate, as many times per frame as the
+/// elapsed time requires; `Input` and `Render` systems run once per frame.
+pub struct Dispatcher {
+    entries: Vec<Entry>,
+    tick: f32,
+    accumulator: f32,
+}
+
+impl Dispatcher {
+    pub fn new(tick_rate: f32) -> Dispatcher {
+        Dispatcher {
+            entries: Vec::new(),
+            tick: 1.0 / tick_rate,
+            accumulator: 0.0,
+        }
+    }
+
+    /// Duration of one update tick in seconds.
+    pub fn tick(&self) -> f32 {
+        self.tick
+    }
+
+    /// Registers an enabled system. `name` identifies it for `set_enabled`
+    /// and in timing reports.
+    pub fn add<S>(&mut self, name: &'static str, stage: Stage, system: S)
+    where
+        S: System + 'static,
+    {
+        assert!(
+            self.entries.iter().all(|e| e.name != name),
+            "system \"{}\" registered twice",
+            name
+        );
+        let index = self.entries
+            .iter()
+            .position(|e| e.stage > stage)
+            .unwrap_or(self.entries.len());
+        self.entries.insert(
+            index,
+            Entry {
+                name,
+                stage,
+                enabled: true,
+                timing: Timing::default(),
+                system: Box::new(system),
+            },
+        );
+    }
+
+    /// Returns false if there is no system called `name`.
+    pub fn set_enabled(&mut self, name: &str, enabled: bool) -> bool {
+        match self.entries.iter_mut().find(|e| e.name == name) {
+            Some(entry) => {
+                entry.enabled = enabled;
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn is_enabled(&self, name: &str) -> Option<bool> {
+        self.entries.iter().find(|e| e.name == name).map(|e| e.enabled)
+    }
+
+    /// Handles pending `ctx.input.dispatcher_requests` and runs one frame that
+    /// took `dt` seconds. Rendering sees `world.alpha` set to how far the
+    /// frame is into the next tick.
+    pub fn run(&mut self, ctx: &mut Context, world: &mut World, dt: f32) {
+        self.handle_requests(ctx);
+        for entry in &mut self.entries {
+            entry.timing.last = Duration::from_secs(0);
+        }
+        self.run_stage(Stage::Input, ctx, world, dt);
+
+        self.accumulator += dt.min(MAX_FRAME_TIME);
+        let mut ticked = false;
+        while self.accumulator >= self.tick {
+            world.save_state();
+            let tick = self.tick;
+            self.run_stage(Stage::Update, ctx, world, tick);
+            self.accumulator -= self.tick;
+            ticked = true;
+        }
+        // without a tick this frame, key presses wait for the next one
+        if ticked {
+            ctx.input.clear_presses();
+        }
+        world.alpha = self.accumulator / self.tick;
+
+        self.run_stage(Stage::Render, ctx, world, dt);
+    }
+
+    fn run_stage(&mut self, stage: Stage, ctx: &mut Context, world: &mut World, dt: f32) {
+        for entry in self.entries.iter_mut().filter(|e| e.enabled && e.stage == stage) {
+            let start = Instant::now();
+            entry.system.run(ctx, world, dt);
+            let elapsed = start.elapsed();
+            entry.timing.last += elapsed;
+            entry.timing.total += elapsed;
+            entry.timing.runs += 1;
+        }
+    }
+
+    /// Every system in run order with its timing.
+    pub fn timings(&self) -> impl Iterator<Item = (&'static str, Stage, Timing)> + '_ {
+        self.entries.iter().map(|e| (e.name, e.stage, e.timing))
+    }
+
+    fn handle_requests(&mut self, ctx: &mut Context) {
+        for request in ctx.input.dispatcher_requests.drain(..) {
+            match request {
+                DispatcherRequest::Toggle(name) => match self.is_enabled(name) {
+                    Some(enabled) => {
+                        self.set_enabled(name, !enabled);
+                        println!("> {} {}", name, if enabled { "disabled" } else { "enabled" });
+                    }
+                    None => println!("> no system called {}", name),
+                },
+                DispatcherRequest::ReportTimings => self.report(),
+            }
+        }
+    }
+
+    fn report(&self) {
+        let ms = |d: Duration| d.as_secs() as f64 * 1e3 + d.subsec_nanos() as f64 / 1e6;
+        println!("> system timings (last frame / average run, ms):");
+        for (name, stage, timing) in self.timings() {
+            let state = if self.is_enabled(name) == Some(true) {
+                ""
+            } else {
+                " (disabled)"
+            };
+            println!(
+                "  {:?} {:<12} {:8.3} / {:8.3}{}",
+                stage,
+                name,
+                ms(timing.last),
+                ms(timing.average()),
+                state
+            );
+        }
+    }
+}
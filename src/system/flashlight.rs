@@ -0,0 +1,24 @@
+use component::Light;
+use context::Context;
+use system::System;
+use world::{Entity, World};
+
+/// Keeps spot lights attached to the camera of their entity. Runs in the
+/// `Render` stage, following the camera as interpolated for the frame, so
+/// that the light does not run ahead of the view.
+pub struct FlashlightSystem;
+
+impl System for FlashlightSystem {
+    fn run(&mut self, _ctx: &mut Context, world: &mut World, _dt: f32) {
+        let entities: Vec<Entity> = world.cameras.iter().map(|(entity, _)| entity).collect();
+        for entity in entities {
+            let camera = match world.camera_of(entity) {
+                Some(camera) => camera,
+                None => continue,
+            };
+            if let Some(&mut Light::Spot(ref mut spot)) = world.lights.get_mut(entity) {
+                spot.follow(&camera);
+            }
+        }
+    }
+}
@@ -0,0 +1,127 @@
+use cgmath::{Matrix4, Vector3};
+use component::{Drawable, Light};
+use context::Context;
+use input::Action;
+use render::LightKind;
+use system::System;
+use world::{Entity, World};
+
+/// Units per second the selected light moves.
+const MOVE_SPEED: f32 = 2.0;
+/// Color change per second of a channel.
+const COLOR_SPEED: f32 = 0.5;
+/// Change per second of the constant, linear and quadratic attenuation.
+const ATTENUATION_SPEED: [f32; 3] = [0.5, 0.05, 0.02];
+
+/// Switches light classes on and off and edits the selected point light.
+/// With the default bindings:
+///
+/// - `1`/`2`/`3` toggle directional, point and spot lights
+/// - `Tab` selects the next point light
+/// - arrow keys and `PageUp`/`PageDown` move it
+/// - `R`/`G`/`B` raise its diffuse and specular color, `J`/`K`/`L` its
+///   constant, linear and quadratic attenuation; holding `LAlt` lowers them
+///
+/// The lamp of the light takes its new specular color. An animated light
+/// stays paused while it is selected and resumes its track afterwards.
+pub struct LightEditSystem;
+
+impl LightEditSystem {
+    fn toggle(world: &mut World, kind: LightKind) {
+        let enabled = !world.is_light_enabled(kind);
+        world.set_light_enabled(kind, enabled);
+        println!("> {} lights {}", kind, if enabled { "on" } else { "off" });
+    }
+
+    fn select_next(world: &mut World) {
+        let points: Vec<Entity> = world
+            .lights
+            .iter()
+            .filter(|&(_, light)| matches!(*light, Light::Point(_)))
+            .map(|(entity, _)| entity)
+            .collect();
+        let next = match world.selected_light {
+            Some(selected) => points.iter().position(|&e| e == selected).map_or(0, |i| i + 1),
+            None => 0,
+        };
+        world.selected_light = points.get(next).cloned();
+        match world.selected_light {
+            Some(_) => println!("> selected point light #{}", next),
+            None => println!("> no point light selected"),
+        }
+    }
+
+    fn edit(ctx: &Context, world: &mut World, entity: Entity, dt: f32) {
+        let held = |action| ctx.input.is_active(action);
+        let axis = |plus, minus| if held(plus) {
+            1.0
+        } else if held(minus) {
+            -1.0
+        } else {
+            0.0
+        };
+        let sign = if held(Action::LightDecrease) { -1.0 } else { 1.0 };
+
+        let movement = Vector3::new(
+            axis(Action::LightRight, Action::LightLeft),
+            axis(Action::LightUp, Action::LightDown),
+            axis(Action::LightBack, Action::LightForward),
+        ) * MOVE_SPEED * dt;
+        if let Some(transform) = world.transforms.get_mut(entity) {
+            transform.model = Matrix4::from_translation(movement) * transform.model;
+        }
+
+        let light = match world.lights.get_mut(entity) {
+            Some(&mut Light::Point(ref mut light)) => light,
+            _ => return,
+        };
+        let colors = [Action::LightRed, Action::LightGreen, Action::LightBlue];
+        for (channel, &action) in colors.iter().enumerate() {
+            if held(action) {
+                let step = sign * COLOR_SPEED * dt;
+                for color in &mut [&mut light.diffuse, &mut light.specular] {
+                    color[channel] = (color[channel] + step).clamp(0.0, 1.0);
+                }
+            }
+        }
+        let mut attenuation = [light.a0, light.a1, light.a2];
+        let factors = [Action::LightConstant, Action::LightLinear, Action::LightQuadratic];
+        for (i, &action) in factors.iter().enumerate() {
+            if held(action) {
+                attenuation[i] = (attenuation[i] + sign * ATTENUATION_SPEED[i] * dt).max(0.0);
+            }
+        }
+        // all zero would divide by zero in the shader
+        if attenuation.iter().any(|&a| a > 0.0) {
+            light.a0 = attenuation[0];
+            light.a1 = attenuation[1];
+            light.a2 = attenuation[2];
+        }
+
+        let specular = Vector3::new(light.specular[0], light.specular[1], light.specular[2]);
+        if let Some(&mut Drawable::Lamp { ref mut color, .. }) = world.drawables.get_mut(entity) {
+            *color = specular;
+        }
+    }
+}
+
+impl System for LightEditSystem {
+    fn run(&mut self, ctx: &mut Context, world: &mut World, dt: f32) {
+        let toggles = [
+            (Action::ToggleDirLights, LightKind::Directional),
+            (Action::TogglePointLights, LightKind::Point),
+            (Action::ToggleSpotLights, LightKind::Spot),
+        ];
+        for &(action, kind) in &toggles {
+            while ctx.input.take_press(action) {
+                LightEditSystem::toggle(world, kind);
+            }
+        }
+        while ctx.input.take_press(Action::SelectNextLight) {
+            LightEditSystem::select_next(world);
+        }
+        if let Some(entity) = world.selected_light {
+            LightEditSystem::edit(ctx, world, entity, dt);
+        }
+    }
+}
@@ -1,11 +1,22 @@
 use context::Context;
+use world::World;
 
+pub mod animation;
 pub mod camera;
+pub mod dispatcher;
+pub mod flashlight;
+pub mod light_edit;
+pub mod render;
 pub mod sysevent;
 
 pub trait System {
-    fn run(&mut self, ctx: &mut Context, dt: f32);
+    fn run(&mut self, ctx: &mut Context, world: &mut World, dt: f32);
 }
 
+pub use self::animation::AnimationSystem;
 pub use self::camera::CameraSystem;
+pub use self::dispatcher::{Dispatcher, DispatcherRequest, Stage};
+pub use self::flashlight::FlashlightSystem;
+pub use self::light_edit::LightEditSystem;
+pub use self::render::RenderSystem;
 pub use self::sysevent::SysEventSystem;
@@ -1,20 +1,193 @@
+use std::path::{Path, PathBuf};
+use gfx;
+use gfx::Device as GfxDevice;
+use gfx_device_gl::{CommandBuffer, Device, Factory, Resources as R};
+use capture;
 use context::Context;
-use gfx_device_gl::Device;
+use camera::Camera;
+use component::Light;
+use render::{self, LampBrush, Lights, ObjectBrush, ShadowBrush, TextBrush};
+use system::System;
+use world::World;
 
+/// Frames still to be written in headless mode.
+struct FrameOutput {
+    dir: PathBuf,
+    next: u32,
+    count: u32,
+}
+
+/// Draws the world and presents the frame, either by swapping buffers or
+/// by writing it to a PNG file in headless mode.
 pub struct RenderSystem {
     device: Device,
+    factory: Factory,
+    encoder: gfx::Encoder<R, CommandBuffer>,
+    object_brush: ObjectBrush<R>,
+    lamp_brush: LampBrush<R>,
+    shadow_brush: ShadowBrush<R>,
+    text_brush: TextBrush<R>,
+    lights: Lights,
+    output: Option<FrameOutput>,
 }
 
 impl RenderSystem {
-    pub fn new(device: Device) -> RenderSystem {
-        RenderSystem { device }
+    /// Shadow maps are `shadow_size` texels square and offset by
+    /// `shadow_bias`.
+    pub fn new(
+        device: Device,
+        mut factory: Factory,
+        shadow_size: u16,
+        shadow_bias: f32,
+    ) -> RenderSystem {
+        let encoder = factory.create_command_buffer().into();
+        let object_brush = ObjectBrush::new(&mut factory);
+        let lamp_brush = LampBrush::new(&mut factory);
+        let shadow_brush = ShadowBrush::new(&mut factory, shadow_size, shadow_bias);
+        let text_brush = TextBrush::new(&mut factory, 2.0);
+        RenderSystem {
+            device,
+            factory,
+            encoder,
+            object_brush,
+            lamp_brush,
+            shadow_brush,
+            text_brush,
+            lights: Lights::new(),
+            output: None,
+        }
+    }
+
+    /// Writes the first `count` frames, at least one, to
+    /// `dir/frame_NNNN.png` instead of presenting them, then stops the
+    /// program. `dir` has to exist.
+    pub fn write_frames<P: AsRef<Path>>(mut self, dir: P, count: u32) -> RenderSystem {
+        self.output = Some(FrameOutput {
+            dir: dir.as_ref().to_path_buf(),
+            next: 0,
+            count,
+        });
+        self
+    }
+
+    /// Draws the world as of a frame that took `dt` seconds.
+    fn draw(&mut self, ctx: &Context, world: &World, dt: f32) {
+        let camera = match world.camera() {
+            Some(camera) => camera,
+            None => return,
+        };
+        let camera = &camera;
+        let batches = render::Batch::group(world.objects());
+        let encoder = &mut self.encoder;
+        self.shadow_brush.draw(&self.lights, &batches, encoder);
+        self.object_brush.begin_frame(camera, &self.lights, encoder);
+        for batch in &batches {
+            self.object_brush.draw(
+                batch,
+                &self.shadow_brush,
+                &ctx.render_target,
+                &ctx.depth_stencil,
+                encoder,
+            );
+        }
+        self.lamp_brush.begin_frame(camera, encoder);
+        self.lamp_brush.draw(world.lamps(), &ctx.render_target, &ctx.depth_stencil, encoder);
+        if ctx.input.overlay {
+            self.draw_overlay(ctx, world, camera, dt);
+        }
     }
-    
-    pub fn run(&mut self, &mut ctx: Context, dt: f32) {
-        for encoder in ctx.encoders.mut_iter() {
-            encoder.flush(&mut self.device);
+
+    fn draw_overlay(&mut self, ctx: &Context, world: &World, camera: &Camera, dt: f32) {
+        let pos = camera.pos();
+        let args = self.lights.args();
+        let frame_time = dt * 1000.0;
+        let mut text = format!(
+            "FPS {:.0}  FRAME {:.2} MS\n\
+             POS {:.2} {:.2} {:.2}\n\
+             YAW {:.1}  PITCH {:.1}  FOV {:.1}{}\n\
+             LIGHTS DIR {}  POINT {}  SPOT {}",
+            ctx.fps,
+            frame_time,
+            pos.x,
+            pos.y,
+            pos.z,
+            camera.yaw(),
+            camera.pitch(),
+            camera.fov(),
+            if camera.is_orbiting() {
+                "  ORBIT"
+            } else if camera.is_free() {
+                "  6DOF"
+            } else {
+                ""
+            },
+            args.num_dir,
+            args.num_point,
+            args.num_spot
+        );
+        let selected = world.selected_light.and_then(|e| world.lights.get(e));
+        if let Some(&Light::Point(light)) = selected {
+            text += &format!(
+                "\nEDIT COLOR {:.2} {:.2} {:.2}  ATT {:.2} {:.3} {:.3}",
+                light.specular[0],
+                light.specular[1],
+                light.specular[2],
+                light.a0,
+                light.a1,
+                light.a2
+            );
+        }
+        let (width, height) = (ctx.input.screen_width as f32, ctx.input.screen_height as f32);
+        self.text_brush.queue(&text, 8.0, 8.0, [1.0, 1.0, 0.0], width, height);
+        self.text_brush.draw(&ctx.render_target, &mut self.encoder);
+    }
+
+    fn save(&mut self, ctx: &Context, path: &Path) -> Result<(), capture::CaptureError> {
+        capture::save_png(
+            &mut self.device,
+            &mut self.factory,
+            &mut self.encoder,
+            &ctx.render_target,
+            path,
+        )
+    }
+}
+
+impl System for RenderSystem {
+    fn run(&mut self, ctx: &mut Context, world: &mut World, dt: f32) {
+        world
+            .collect_lights(&mut self.lights)
+            .expect("light limits are checked when spawning the scene");
+        self.encoder.clear(&ctx.render_target, render::BG);
+        self.encoder.clear_depth(&ctx.depth_stencil, 1.0);
+        self.draw(ctx, world, dt);
+
+        if let Some(path) = self.output.as_ref().map(|out| {
+            out.dir.join(format!("frame_{:04}.png", out.next))
+        })
+        {
+            if let Err(e) = self.save(ctx, &path) {
+                eprintln!("{}: {}", path.display(), e);
+                ctx.failed = true;
+                return;
+            }
+            let out = self.output.as_mut().unwrap();
+            out.next += 1;
+            if out.next >= out.count {
+                ctx.input.running = false;
+            }
+        } else {
+            if ctx.input.screenshot_requested {
+                ctx.input.screenshot_requested = false;
+                let path = capture::screenshot_path();
+                match self.save(ctx, &path) {
+                    Ok(()) => println!("> saved {}", path.display()),
+                    Err(e) => eprintln!("{}: {}", path.display(), e),
+                }
+            }
+            self.encoder.flush(&mut self.device);
+            ctx.surface.swap_buffers();
         }
-        ctx.window.swap_buffers().unwrap();
         self.device.cleanup();
     }
-}
\ No newline at end of file
+}
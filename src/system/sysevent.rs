@@ -1,74 +1,77 @@
-use glutin;
+use std::vec;
 use glutin::{EventsLoop, Event};
-use gfx_window_glutin;
+use input::InputEvent;
+use replay::{InputFrame, InputRecorder};
 use system::System;
 use context::Context;
+use world::World;
 
+/// Feeds the window's input into the `Context`, optionally recording it.
 pub struct SysEventSystem {
-    events_loop: EventsLoop,
+    events_loop: Option<EventsLoop>,
+    recorder: Option<InputRecorder>,
+    /// Recorded frames fed instead of the window's input, one per run; only
+    /// closing the window is still handled.
+    replay: Option<vec::IntoIter<InputFrame>>,
 }
 
 impl SysEventSystem {
     pub fn new(events_loop: EventsLoop) -> SysEventSystem {
-        SysEventSystem { events_loop }
+        SysEventSystem {
+            events_loop: Some(events_loop),
+            recorder: None,
+            replay: None,
+        }
     }
 
-    fn update(event: Event, ctx: &mut Context) {
-        use glutin::WindowEvent::*;
-        use glutin::{MouseScrollDelta, VirtualKeyCode};
-        use glutin::ElementState::*;
-        if let Event::WindowEvent { event, .. } = event {
-            match event {
-                Closed => {
-                    ctx.running = false; // cannot `break` in closure
-                }
-                KeyboardInput {
-                    input:
-                        glutin::KeyboardInput {
-                            state,
-                            virtual_keycode: Some(vk),
-                            ..
-                        },
-                    ..
-                } => match (state, vk) {
-                    (_, VirtualKeyCode::Escape) => ctx.running = false,
-                    _ => {
-                        ctx.key_state.update_key(vk, state == Pressed);
-                    }
-                },
-                MouseMoved {
-                    position: (x, y), ..
-                } => {
-                    ctx.update_mouse_pos(x as i32, y as i32);
-                }
-                Focused(true) => {
-                    ctx.focused();
-                }
-                MouseEntered { .. } => {
-                    ctx.mouse_entered();
-                }
-                MouseWheel {
-                    delta: MouseScrollDelta::LineDelta(_, dy),
-                    ..
-                } => {
-                    ctx.mouse_state.update_scroll(dy);
-                }
-                Resized(w, h) => {
-                    ctx.update_dimensions(w, h);
-                    gfx_window_glutin::update_views(
-                        &ctx.window,
-                        &mut ctx.render_target,
-                        &mut ctx.depth_stencil,
-                    );
-                }
-                _ => {}
-            }
+    /// Without a window there is no input other than a replayed one.
+    pub fn headless() -> SysEventSystem {
+        SysEventSystem {
+            events_loop: None,
+            recorder: None,
+            replay: None,
         }
     }
+
+    /// Writes every frame's input to `recorder`.
+    pub fn record(mut self, recorder: InputRecorder) -> SysEventSystem {
+        self.recorder = Some(recorder);
+        self
+    }
+
+    /// Feeds the input of `frames` instead of the window's, stopping the
+    /// program after the last one.
+    pub fn replay(mut self, frames: Vec<InputFrame>) -> SysEventSystem {
+        self.replay = Some(frames.into_iter());
+        self
+    }
 }
 
 impl System for SysEventSystem {
-    fn run(&mut self, ctx: &mut Context, _dt: f32) {
-        self.events_loop.poll_events(|event| { SysEventSystem::update(event, ctx); });
+    fn run(&mut self, ctx: &mut Context, _world: &mut World, dt: f32) {
+        let mut events = Vec::new();
+        if let Some(ref mut events_loop) = self.events_loop {
+            events_loop.poll_events(|event| {
+                if let Event::WindowEvent { event, .. } = event {
+                    events.extend(InputEvent::from_glutin(&event));
+                }
+            });
+        }
+        if let Some(ref mut frames) = self.replay {
+            events.retain(|&event| event == InputEvent::Close);
+            match frames.next() {
+                Some(frame) => events.extend(frame.events),
+                None => ctx.input.running = false,
+            }
+        }
+        for &event in &events {
+            ctx.handle(event);
+        }
+        if let Some(ref mut recorder) = self.recorder {
+            if let Err(e) = recorder.record(&InputFrame { dt, events }) {
+                eprintln!("cannot record input: {}", e);
+                self.recorder = None;
+            }
+        }
     }
 }
@@ -0,0 +1,274 @@
+use std::rc::Rc;
+use cgmath::Matrix4;
+use gfx_device_gl::Resources as R;
+use camera::Camera;
+use component::{Animation, Drawable, Light, Transform};
+use render::{self, LampInstance, LightCapacityError, LightKind, Material, Mesh};
+
+/// Identifies a set of components in a `World`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct Entity(usize);
+
+/// Components of one type, indexed by entity.
+#[derive(Clone)]
+pub struct Storage<T> {
+    items: Vec<Option<T>>,
+}
+
+impl<T> Storage<T> {
+    pub fn new() -> Storage<T> {
+        Storage { items: Vec::new() }
+    }
+
+    pub fn insert(&mut self, entity: Entity, component: T) {
+        if entity.0 >= self.items.len() {
+            self.items.resize_with(entity.0 + 1, || None);
+        }
+        self.items[entity.0] = Some(component);
+    }
+
+    #[allow(dead_code)]
+    pub fn remove(&mut self, entity: Entity) -> Option<T> {
+        self.items.get_mut(entity.0).and_then(|c| c.take())
+    }
+
+    pub fn get(&self, entity: Entity) -> Option<&T> {
+        self.items.get(entity.0).and_then(|c| c.as_ref())
+    }
+
+    pub fn get_mut(&mut self, entity: Entity) -> Option<&mut T> {
+        self.items.get_mut(entity.0).and_then(|c| c.as_mut())
+    }
+
+    /// Every component with its entity, in creation order.
+    pub fn iter(&self) -> impl Iterator<Item = (Entity, &T)> {
+        self.items
+            .iter()
+            .enumerate()
+            .filter_map(|(i, c)| c.as_ref().map(|c| (Entity(i), c)))
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = (Entity, &mut T)> {
+        self.items
+            .iter_mut()
+            .enumerate()
+            .filter_map(|(i, c)| c.as_mut().map(|c| (Entity(i), c)))
+    }
+
+    /// Pairs every component with the entity's component in `other`,
+    /// skipping entities that lack it.
+    #[allow(dead_code)]
+    pub fn join<'a, U>(
+        &'a self,
+        other: &'a Storage<U>,
+    ) -> impl Iterator<Item = (Entity, &'a T, &'a U)> {
+        self.iter()
+            .filter_map(move |(e, c)| other.get(e).map(|o| (e, c, o)))
+    }
+}
+
+impl<T> Default for Storage<T> {
+    fn default() -> Storage<T> {
+        Storage::new()
+    }
+}
+
+/// A component type, stored in one of the `World` storages.
+pub trait Component: Sized {
+    fn storage(world: &mut World) -> &mut Storage<Self>;
+}
+
+impl Component for Transform {
+    fn storage(world: &mut World) -> &mut Storage<Transform> {
+        &mut world.transforms
+    }
+}
+
+impl Component for Drawable<R> {
+    fn storage(world: &mut World) -> &mut Storage<Drawable<R>> {
+        &mut world.drawables
+    }
+}
+
+impl Component for Light {
+    fn storage(world: &mut World) -> &mut Storage<Light> {
+        &mut world.lights
+    }
+}
+
+impl Component for Animation {
+    fn storage(world: &mut World) -> &mut Storage<Animation> {
+        &mut world.animations
+    }
+}
+
+impl Component for Camera {
+    fn storage(world: &mut World) -> &mut Storage<Camera> {
+        &mut world.cameras
+    }
+}
+
+pub struct World {
+    next: usize,
+    pub transforms: Storage<Transform>,
+    pub drawables: Storage<Drawable<R>>,
+    pub lights: Storage<Light>,
+    pub cameras: Storage<Camera>,
+    pub animations: Storage<Animation>,
+    /// Transforms and cameras as they were before the latest update tick.
+    previous_transforms: Storage<Transform>,
+    previous_cameras: Storage<Camera>,
+    /// How far rendering is between the previous and the current tick, from
+    /// 0 to 1.
+    pub alpha: f32,
+    /// Light classes switched off as a whole, by `LightKind::index`.
+    light_disabled: [bool; 3],
+    /// The light edited with the keyboard, if any.
+    pub selected_light: Option<Entity>,
+}
+
+impl Default for World {
+    fn default() -> World {
+        World {
+            next: 0,
+            transforms: Storage::new(),
+            drawables: Storage::new(),
+            lights: Storage::new(),
+            cameras: Storage::new(),
+            animations: Storage::new(),
+            previous_transforms: Storage::new(),
+            previous_cameras: Storage::new(),
+            alpha: 0.0,
+            light_disabled: [false; 3],
+            selected_light: None,
+        }
+    }
+}
+
+impl World {
+    pub fn new() -> World {
+        World::default()
+    }
+
+    pub fn create_entity(&mut self) -> EntityBuilder<'_> {
+        let entity = Entity(self.next);
+        self.next += 1;
+        EntityBuilder {
+            world: self,
+            entity,
+        }
+    }
+
+    /// Remembers the current transforms and cameras, to interpolate from
+    /// them while the next tick is rendered. Called before every update tick.
+    pub fn save_state(&mut self) {
+        self.previous_transforms = self.transforms.clone();
+        self.previous_cameras = self.cameras.clone();
+    }
+
+    /// The transform of `entity` at `alpha` between the previous and the
+    /// current tick.
+    pub fn transform(&self, entity: Entity) -> Option<Transform> {
+        let current = self.transforms.get(entity)?;
+        Some(match self.previous_transforms.get(entity) {
+            Some(previous) => previous.lerp(current, self.alpha),
+            None => *current,
+        })
+    }
+
+    /// The camera of `entity`, interpolated like `transform`.
+    pub fn camera_of(&self, entity: Entity) -> Option<Camera> {
+        let current = self.cameras.get(entity)?;
+        Some(match self.previous_cameras.get(entity) {
+            Some(previous) => previous.lerp(current, self.alpha),
+            None => current.clone(),
+        })
+    }
+
+    /// The first camera, the one the scene is viewed through, interpolated
+    /// like `transform`.
+    pub fn camera(&self) -> Option<Camera> {
+        let (entity, _) = self.cameras.iter().next()?;
+        self.camera_of(entity)
+    }
+
+    /// Lit drawables with their interpolated model matrices, for
+    /// `render::Batch::group`.
+    pub fn objects(&self) -> impl Iterator<Item = (&Rc<Mesh<R>>, &Material<R>, Matrix4<f32>)> {
+        self.drawables.iter().filter_map(move |(entity, drawable)| {
+            match *drawable {
+                Drawable::Object {
+                    ref mesh,
+                    ref material,
+                } => self.transform(entity).map(|t| (mesh, material, t.model)),
+                Drawable::Lamp { .. } => None,
+            }
+        })
+    }
+
+    /// Lamp drawables, interpolated, for `render::LampBrush::draw`.
+    pub fn lamps(&self) -> impl Iterator<Item = (&Rc<Mesh<R>>, LampInstance)> {
+        self.drawables.iter().filter_map(move |(entity, drawable)| {
+            match *drawable {
+                Drawable::Lamp { ref mesh, color } => {
+                    self.transform(entity)
+                        .map(|t| (mesh, LampInstance::new(t.model, color)))
+                }
+                Drawable::Object { .. } => None,
+            }
+        })
+    }
+
+    pub fn set_light_enabled(&mut self, kind: LightKind, enabled: bool) {
+        self.light_disabled[kind.index()] = !enabled;
+    }
+
+    pub fn is_light_enabled(&self, kind: LightKind) -> bool {
+        !self.light_disabled[kind.index()]
+    }
+
+    /// Replaces the contents of `lights` with every light in the world.
+    /// Point and spot lights with a `Transform` are placed at its origin.
+    pub fn collect_lights(&self, lights: &mut render::Lights) -> Result<(), LightCapacityError> {
+        lights.clear();
+        for &kind in &LightKind::all() {
+            lights.set_enabled(kind, self.is_light_enabled(kind));
+        }
+        for (entity, light) in self.lights.iter() {
+            let pos = self.transform(entity)
+                .map(|t| t.position().extend(1.0).into());
+            match *light {
+                Light::Directional(light) => lights.add_dir(light)?,
+                Light::Point(mut light) => {
+                    if let Some(pos) = pos {
+                        light.pos = pos;
+                    }
+                    lights.add_point(light)?
+                }
+                Light::Spot(mut light) => {
+                    if let Some(pos) = pos {
+                        light.pos = pos;
+                    }
+                    lights.add_spot(light)?
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+pub struct EntityBuilder<'a> {
+    world: &'a mut World,
+    entity: Entity,
+}
+
+impl<'a> EntityBuilder<'a> {
+    pub fn with<C: Component>(self, component: C) -> EntityBuilder<'a> {
+        C::storage(self.world).insert(self.entity, component);
+        self
+    }
+
+    pub fn build(self) -> Entity {
+        self.entity
+    }
+}